@@ -0,0 +1,10 @@
+//! # EVM executors
+//!
+//! Executors are structs that hook gasometer and the EVM core together. It
+//! also handles the call stacks in EVM.
+
+mod stack;
+mod tracer;
+
+pub use self::stack::{StackAccount, StackExecutor};
+pub use self::tracer::{CallType, Frame, Step, Tracer};
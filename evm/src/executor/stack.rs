@@ -0,0 +1,915 @@
+use core::convert::Infallible;
+use core::cmp::min;
+use core::cell::RefCell;
+use alloc::rc::Rc;
+use alloc::vec::Vec;
+use alloc::collections::{BTreeMap, BTreeSet};
+use primitive_types::{U256, H256, H160};
+use sha3::{Keccak256, Digest};
+use crate::{ExitError, Stack, ExternalOpcode, Opcode, Capture, Handler, Transfer,
+			Context, CreateScheme, Runtime, ExitReason, ExitSucceed, Config};
+use crate::backend::{Log, Basic, Apply, Backend};
+use crate::gasometer::{self, Gasometer};
+use super::tracer::{CallType, Frame, Step, Tracer};
+
+/// Account definition for the stack-based executor.
+#[derive(Default, Clone, Debug, Eq, PartialEq)]
+pub struct StackAccount {
+	/// Basic account information, including nonce and balance.
+	pub basic: Basic,
+	/// Code. `None` means the code is currently unknown.
+	pub code: Option<Vec<u8>>,
+	/// Storage. Not inserted values mean it is currently known, but not empty.
+	pub storage: BTreeMap<H256, H256>,
+	/// Whether the storage in the database should be reset before storage
+	/// values are applied.
+	pub reset_storage: bool,
+}
+
+/// Stack-based executor.
+#[derive(Clone)]
+pub struct StackExecutor<'backend, 'config, B> {
+	backend: &'backend B,
+	config: &'config Config,
+	gasometer: Gasometer<'config>,
+	state: BTreeMap<H160, StackAccount>,
+	deleted: BTreeSet<H160>,
+	logs: Vec<Log>,
+	precompile: fn(H160, &[u8], Option<usize>) -> Option<Result<(ExitSucceed, Vec<u8>, usize), ExitError>>,
+	is_static: bool,
+	depth: Option<usize>,
+	tracer: Option<Rc<RefCell<dyn Tracer>>>,
+}
+
+fn no_precompile(
+	_address: H160,
+	_input: &[u8],
+	_target_gas: Option<usize>
+) -> Option<Result<(ExitSucceed, Vec<u8>, usize), ExitError>> {
+	None
+}
+
+impl<'backend, 'config, B: Backend> StackExecutor<'backend, 'config, B> {
+	/// Create a new stack-based executor.
+	pub fn new(
+		backend: &'backend B,
+		gas_limit: usize,
+		config: &'config Config,
+	) -> Self {
+		Self::new_with_precompile(backend, gas_limit, config, no_precompile)
+	}
+
+	/// Create a new stack-based executor with given precompiles.
+	pub fn new_with_precompile(
+		backend: &'backend B,
+		gas_limit: usize,
+		config: &'config Config,
+		precompile: fn(H160, &[u8], Option<usize>) -> Option<Result<(ExitSucceed, Vec<u8>, usize), ExitError>>,
+	) -> Self {
+		Self {
+			backend,
+			gasometer: Gasometer::new(gas_limit, config),
+			state: BTreeMap::new(),
+			deleted: BTreeSet::new(),
+			config,
+			logs: Vec::new(),
+			precompile: precompile,
+			is_static: false,
+			depth: None,
+			tracer: None,
+		}
+	}
+
+	/// Create a substate executor from the current executor.
+	pub fn substate(&self, gas_limit: usize, is_static: bool) -> StackExecutor<'backend, 'config, B> {
+		Self {
+			backend: self.backend,
+			gasometer: Gasometer::new(gas_limit, self.gasometer.config()),
+			config: self.config,
+			state: self.state.clone(),
+			deleted: self.deleted.clone(),
+			logs: Vec::new(),
+			precompile: self.precompile,
+			is_static: is_static || self.is_static,
+			depth: match self.depth {
+				None => Some(0),
+				Some(n) => Some(n + 1),
+			},
+			tracer: self.tracer.clone(),
+		}
+	}
+
+	/// Set the tracer observing the execution of this executor and its substates.
+	pub fn set_tracer(&mut self, tracer: Rc<RefCell<dyn Tracer>>) {
+		self.tracer = Some(tracer);
+	}
+
+	/// Execute the runtime until it returns.
+	pub fn execute(&mut self, runtime: &mut Runtime) -> ExitReason {
+		match runtime.run(self) {
+			Capture::Exit(s) => s,
+			Capture::Trap(_) => unreachable!("Trap is Infallible"),
+		}
+	}
+
+	/// Execute the runtime of a call frame until it returns, stepping it through the tracer if
+	/// there is one.
+	fn execute_frame(&mut self, runtime: &mut Runtime, code: &[u8], address: H160) -> ExitReason {
+		let tracer = match self.tracer.clone() {
+			Some(tracer) => tracer,
+			None => return self.execute(runtime),
+		};
+		let depth = self.depth.map_or(1, |d| d + 1);
+
+		let mut pc = 0;
+		loop {
+			let gas = self.gasometer.gas();
+			let next = match runtime.machine().inspect() {
+				Some((_, stack)) => {
+					let opcode = code[pc];
+					tracer.borrow_mut().step(&Step {
+						depth,
+						address,
+						pc,
+						opcode,
+						gas,
+						stack,
+						memory: runtime.machine().memory(),
+					});
+					Some(next_pc(pc, opcode, stack))
+				},
+				None => None,
+			};
+
+			let exit = match runtime.step(self) {
+				Ok(()) => None,
+				Err(Capture::Exit(s)) => Some(s),
+				Err(Capture::Trap(_)) => unreachable!("Trap is Infallible"),
+			};
+
+			if let Some(next) = next {
+				let gas_cost = gas.saturating_sub(self.gasometer.gas());
+				tracer.borrow_mut().step_result(gas_cost, runtime.machine().stack());
+				pc = next;
+			}
+
+			if let Some(reason) = exit {
+				return reason
+			}
+		}
+	}
+
+	fn trace_enter(&self, frame: &Frame) {
+		if let Some(tracer) = &self.tracer {
+			tracer.borrow_mut().enter(frame);
+		}
+	}
+
+	fn trace_exit(&self, reason: &ExitReason, gas_used: usize, output: &[u8]) {
+		if let Some(tracer) = &self.tracer {
+			tracer.borrow_mut().exit(reason, gas_used, output);
+		}
+	}
+
+	/// Get remaining gas.
+	pub fn gas(&self) -> usize {
+		self.gasometer.gas()
+	}
+
+	/// Merge a substate executor that succeeded.
+	pub fn merge_succeed<'obackend, 'oconfig, OB>(
+		&mut self,
+		mut substate: StackExecutor<'obackend, 'oconfig, OB>
+	) -> Result<(), ExitError> {
+		self.logs.append(&mut substate.logs);
+		self.deleted.append(&mut substate.deleted);
+		self.state = substate.state;
+
+		self.gasometer.record_stipend(substate.gasometer.gas())?;
+		self.gasometer.record_refund(substate.gasometer.refunded_gas())?;
+		Ok(())
+	}
+
+	/// Merge a substate executor that reverted.
+	pub fn merge_revert<'obackend, 'oconfig, OB>(
+		&mut self,
+		substate: StackExecutor<'obackend, 'oconfig, OB>
+	) -> Result<(), ExitError> {
+		self.gasometer.record_stipend(substate.gasometer.gas())?;
+		Ok(())
+	}
+
+	/// Merge a substate executor that failed.
+	pub fn merge_fail<'obackend, 'oconfig, OB>(
+		&mut self,
+		_substate: StackExecutor<'obackend, 'oconfig, OB>
+	) -> Result<(), ExitError> {
+		Ok(())
+	}
+
+	/// Execute a `CREATE` transaction, returning the exit reason and the revert data, if any.
+	pub fn transact_create(
+		&mut self,
+		caller: H160,
+		value: U256,
+		init_code: Vec<u8>,
+		gas_limit: usize,
+	) -> (ExitReason, Vec<u8>) {
+		let transaction_cost = gasometer::create_transaction_cost(&init_code);
+		match self.gasometer.record_transaction(transaction_cost) {
+			Ok(()) => (),
+			Err(e) => return (e.into(), Vec::new()),
+		}
+
+		match self.create_inner(
+			caller,
+			CreateScheme::Legacy { caller },
+			value,
+			init_code,
+			Some(gas_limit),
+			false,
+		) {
+			Capture::Exit((s, _, v)) => (s, v),
+			Capture::Trap(_) => unreachable!(),
+		}
+	}
+
+	/// Execute a `CREATE2` transaction, returning the exit reason and the revert data, if any.
+	pub fn transact_create2(
+		&mut self,
+		caller: H160,
+		value: U256,
+		init_code: Vec<u8>,
+		salt: H256,
+		gas_limit: usize,
+	) -> (ExitReason, Vec<u8>) {
+		let transaction_cost = gasometer::create_transaction_cost(&init_code);
+		match self.gasometer.record_transaction(transaction_cost) {
+			Ok(()) => (),
+			Err(e) => return (e.into(), Vec::new()),
+		}
+		let code_hash = H256::from_slice(Keccak256::digest(&init_code).as_slice());
+
+		match self.create_inner(
+			caller,
+			CreateScheme::Create2 { caller, code_hash, salt },
+			value,
+			init_code,
+			Some(gas_limit),
+			false,
+		) {
+			Capture::Exit((s, _, v)) => (s, v),
+			Capture::Trap(_) => unreachable!(),
+		}
+	}
+
+	/// Execute a `CALL` transaction, returning the exit reason and the return data.
+	pub fn transact_call(
+		&mut self,
+		caller: H160,
+		address: H160,
+		value: U256,
+		data: Vec<u8>,
+		gas_limit: usize,
+	) -> (ExitReason, Vec<u8>) {
+		let transaction_cost = gasometer::call_transaction_cost(&data);
+		match self.gasometer.record_transaction(transaction_cost) {
+			Ok(()) => (),
+			Err(e) => return (e.into(), Vec::new()),
+		}
+
+		self.account_mut(caller).basic.nonce += U256::one();
+
+		let context = Context {
+			caller,
+			address,
+			apparent_value: value,
+		};
+
+		match self.call_inner(address, Some(Transfer {
+			source: caller,
+			target: address,
+			value
+		}), data, Some(gas_limit), false, false, false, context) {
+			Capture::Exit((s, v)) => (s, v),
+			Capture::Trap(_) => unreachable!(),
+		}
+	}
+
+	/// Get used gas for the current executor, after refunds.
+	pub fn used_gas(
+		&self,
+	) -> usize {
+		self.gasometer.total_used_gas() -
+			min(self.gasometer.total_used_gas() / 2, self.gasometer.refunded_gas() as usize)
+	}
+
+	/// Get fee needed for the current executor, given the price.
+	pub fn fee(
+		&self,
+		price: U256,
+	) -> U256 {
+		U256::from(self.used_gas()) * price
+	}
+
+	/// Deconstruct the executor, return state to be applied.
+	#[must_use]
+	pub fn deconstruct(
+		self
+	) -> (impl IntoIterator<Item=Apply<impl IntoIterator<Item=(H256, H256)>>>,
+		  impl IntoIterator<Item=Log>)
+	{
+		let mut applies = Vec::<Apply<BTreeMap<H256, H256>>>::new();
+
+		for (address, account) in self.state {
+			if self.deleted.contains(&address) {
+				continue
+			}
+
+			applies.push(Apply::Modify {
+				address,
+				basic: account.basic,
+				code: account.code,
+				storage: account.storage,
+				reset_storage: account.reset_storage,
+			});
+		}
+
+		for address in self.deleted {
+			applies.push(Apply::Delete { address });
+		}
+
+		let logs = self.logs;
+
+		(applies, logs)
+	}
+
+	/// Get mutable account reference.
+	pub fn account_mut(&mut self, address: H160) -> &mut StackAccount {
+		self.state.entry(address).or_insert(StackAccount {
+			basic: self.backend.basic(address),
+			code: None,
+			storage: BTreeMap::new(),
+			reset_storage: false,
+		})
+	}
+
+	/// Get account nonce.
+	pub fn nonce(&self, address: H160) -> U256 {
+		self.state.get(&address).map(|v| v.basic.nonce)
+			.unwrap_or(self.backend.basic(address).nonce)
+	}
+
+	/// Withdraw balance from address.
+	pub fn withdraw(&mut self, address: H160, balance: U256) -> Result<(), ExitError> {
+		let source = self.account_mut(address);
+		if source.basic.balance < balance {
+			return Err(ExitError::OutOfFund.into())
+		}
+		source.basic.balance -= balance;
+
+		Ok(())
+	}
+
+	/// Deposit balance to address.
+	pub fn deposit(&mut self, address: H160, balance: U256) {
+		let target = self.account_mut(address);
+		target.basic.balance += balance;
+	}
+
+	/// Transfer balance with the given struct.
+	pub fn transfer(&mut self, transfer: Transfer) -> Result<(), ExitError> {
+		self.withdraw(transfer.source, transfer.value)?;
+		self.deposit(transfer.target, transfer.value);
+
+		Ok(())
+	}
+
+	/// Get the create address from given scheme.
+	pub fn create_address(&self, scheme: CreateScheme) -> H160 {
+		match scheme {
+			CreateScheme::Create2 { caller, code_hash, salt } => {
+				let mut hasher = Keccak256::new();
+				hasher.input(&[0xff]);
+				hasher.input(&caller[..]);
+				hasher.input(&salt[..]);
+				hasher.input(&code_hash[..]);
+				H256::from_slice(hasher.result().as_slice()).into()
+			},
+			CreateScheme::Legacy { caller } => {
+				let nonce = self.nonce(caller);
+				let mut stream = rlp::RlpStream::new_list(2);
+				stream.append(&caller);
+				stream.append(&nonce);
+				H256::from_slice(Keccak256::digest(&stream.out()).as_slice()).into()
+			},
+			CreateScheme::Fixed(naddress) => {
+				naddress
+			},
+		}
+	}
+
+	fn create_inner(
+		&mut self,
+		caller: H160,
+		scheme: CreateScheme,
+		value: U256,
+		init_code: Vec<u8>,
+		target_gas: Option<usize>,
+		take_l64: bool,
+	) -> Capture<(ExitReason, Option<H160>, Vec<u8>), Infallible> {
+		macro_rules! try_or_fail {
+			( $e:expr ) => {
+				match $e {
+					Ok(v) => v,
+					Err(e) => return Capture::Exit((e.into(), None, Vec::new())),
+				}
+			}
+		}
+
+		// Exit a frame which failed before executing its code.
+		macro_rules! fail {
+			( $reason:expr, $gas_used:expr ) => {{
+				let reason = ExitReason::from($reason);
+				self.trace_exit(&reason, $gas_used, &[]);
+				return Capture::Exit((reason, None, Vec::new()))
+			}}
+		}
+
+		fn l64(gas: usize) -> usize {
+			gas - gas / 64
+		}
+
+		let mut after_gas = self.gasometer.gas();
+		if take_l64 && self.config.call_l64_after_gas {
+			after_gas = l64(after_gas);
+		}
+		let target_gas = target_gas.unwrap_or(after_gas);
+
+		let gas_limit = min(after_gas, target_gas);
+		let address = self.create_address(scheme);
+
+		// Frames failing before their code runs are traced too, as geth does.
+		self.trace_enter(&Frame {
+			call_type: match scheme {
+				CreateScheme::Create2 { .. } => CallType::Create2,
+				_ => CallType::Create,
+			},
+			from: caller,
+			to: address,
+			value,
+			gas: gas_limit,
+			input: &init_code,
+		});
+
+		if let Some(depth) = self.depth {
+			if depth + 1 > self.config.call_stack_limit {
+				fail!(ExitError::CallTooDeep, 0)
+			}
+		}
+
+		if self.balance(caller) < value {
+			fail!(ExitError::OutOfFund, 0)
+		}
+
+		if let Err(e) = self.gasometer.record_cost(gas_limit) {
+			fail!(e, 0)
+		}
+
+		self.account_mut(caller).basic.nonce += U256::one();
+
+		let mut substate = self.substate(gas_limit, false);
+		{
+			if let Some(code) = substate.account_mut(address).code.as_ref() {
+				if code.len() != 0 {
+					let _ = self.merge_fail(substate);
+					fail!(ExitError::CreateCollision, gas_limit)
+				}
+			} else  {
+				let code = substate.backend.code(address);
+				substate.account_mut(address).code = Some(code.clone());
+
+				if code.len() != 0 {
+					let _ = self.merge_fail(substate);
+					fail!(ExitError::CreateCollision, gas_limit)
+				}
+			}
+
+			if substate.account_mut(address).basic.nonce > U256::zero() {
+				let _ = self.merge_fail(substate);
+				fail!(ExitError::CreateCollision, gas_limit)
+			}
+
+			substate.account_mut(address).reset_storage = true;
+			substate.account_mut(address).storage = BTreeMap::new();
+		}
+
+		let context = Context {
+			address,
+			caller,
+			apparent_value: value,
+		};
+		let transfer = Transfer {
+			source: caller,
+			target: address,
+			value,
+		};
+		match substate.transfer(transfer) {
+			Ok(()) => (),
+			Err(e) => {
+				let _ = self.merge_revert(substate);
+				fail!(e, 0)
+			},
+		}
+
+		if self.config.create_increase_nonce {
+			substate.account_mut(address).basic.nonce += U256::one();
+		}
+
+		let code = Rc::new(init_code);
+		let mut runtime = Runtime::new(
+			code.clone(),
+			Rc::new(Vec::new()),
+			context,
+			self.config,
+		);
+
+		let reason = substate.execute_frame(&mut runtime, &code, address);
+
+		match reason {
+			ExitReason::Succeed(s) => {
+				let out = runtime.machine().return_value();
+
+				if let Some(limit) = self.config.create_contract_limit {
+					if out.len() > limit {
+						substate.gasometer.fail();
+						let _ = self.merge_fail(substate);
+						self.trace_exit(&ExitError::CreateContractLimit.into(), gas_limit, &[]);
+						return Capture::Exit((ExitError::CreateContractLimit.into(), None, Vec::new()))
+					}
+				}
+
+				match substate.gasometer.record_deposit(out.len()) {
+					Ok(()) => {
+						self.trace_exit(&ExitReason::Succeed(s), gas_limit - substate.gas(), &out);
+						let e = self.merge_succeed(substate);
+						self.state.entry(address).or_insert(Default::default())
+							.code = Some(out);
+						try_or_fail!(e);
+						Capture::Exit((ExitReason::Succeed(s), Some(address), Vec::new()))
+					},
+					Err(e) => {
+						let _ = self.merge_fail(substate);
+						self.trace_exit(&ExitReason::Error(e), gas_limit, &[]);
+						Capture::Exit((ExitReason::Error(e), None, Vec::new()))
+					},
+				}
+			},
+			ExitReason::Error(e) => {
+				substate.gasometer.fail();
+				let _ = self.merge_fail(substate);
+				self.trace_exit(&ExitReason::Error(e), gas_limit, &[]);
+				Capture::Exit((ExitReason::Error(e), None, Vec::new()))
+			},
+			ExitReason::Revert(e) => {
+				let out = runtime.machine().return_value();
+				self.trace_exit(&ExitReason::Revert(e), gas_limit - substate.gas(), &out);
+				let _ = self.merge_revert(substate);
+				Capture::Exit((ExitReason::Revert(e), None, out))
+			},
+			ExitReason::Fatal(e) => {
+				self.gasometer.fail();
+				self.trace_exit(&ExitReason::Fatal(e), gas_limit, &[]);
+				Capture::Exit((ExitReason::Fatal(e), None, Vec::new()))
+			},
+		}
+	}
+
+	fn call_inner(
+		&mut self,
+		code_address: H160,
+		transfer: Option<Transfer>,
+		input: Vec<u8>,
+		target_gas: Option<usize>,
+		is_static: bool,
+		take_l64: bool,
+		take_stipend: bool,
+		context: Context,
+	) -> Capture<(ExitReason, Vec<u8>), Infallible> {
+		// Exit a frame which failed before executing its code.
+		macro_rules! fail {
+			( $reason:expr ) => {{
+				let reason = ExitReason::from($reason);
+				self.trace_exit(&reason, 0, &[]);
+				return Capture::Exit((reason, Vec::new()))
+			}}
+		}
+
+		fn l64(gas: usize) -> usize {
+			gas - gas / 64
+		}
+
+		let mut after_gas = self.gasometer.gas();
+		if take_l64 && self.config.call_l64_after_gas {
+			after_gas = l64(after_gas);
+		}
+
+		let target_gas = target_gas.unwrap_or(after_gas);
+		let mut gas_limit = min(target_gas, after_gas);
+		let stipend = match transfer.as_ref() {
+			Some(transfer) if take_stipend && transfer.value != U256::zero() => self.config.call_stipend,
+			_ => 0,
+		};
+
+		// The frame of a transaction is always reported as a call.
+		let call_type = match transfer {
+			_ if self.depth.is_none() => CallType::Call,
+			_ if is_static => CallType::StaticCall,
+			None => CallType::DelegateCall,
+			Some(_) if context.address != code_address => CallType::CallCode,
+			Some(_) => CallType::Call,
+		};
+		let value = transfer.as_ref().map_or(U256::zero(), |t| t.value);
+
+		// Frames failing before their code runs are traced too, as geth does.
+		self.trace_enter(&Frame {
+			call_type,
+			from: match call_type {
+				CallType::DelegateCall => context.address,
+				_ => context.caller,
+			},
+			to: code_address,
+			value,
+			gas: gas_limit.saturating_add(stipend),
+			input: &input,
+		});
+
+		if let Err(e) = self.gasometer.record_cost(gas_limit) {
+			fail!(e)
+		}
+		gas_limit = gas_limit.saturating_add(stipend);
+
+		let code = self.code(code_address);
+
+		let mut substate = self.substate(gas_limit, is_static);
+		substate.account_mut(context.address);
+
+		if let Some(depth) = self.depth {
+			if depth + 1 > self.config.call_stack_limit {
+				let _ = self.merge_revert(substate);
+				fail!(ExitError::CallTooDeep)
+			}
+		}
+
+		if let Some(transfer) = transfer {
+			match substate.transfer(transfer) {
+				Ok(()) => (),
+				Err(e) => {
+					let _ = self.merge_revert(substate);
+					fail!(e)
+				},
+			}
+		}
+
+		if let Some(ret) = (substate.precompile)(code_address, &input, Some(gas_limit)) {
+			return match ret {
+				Ok((s, out, cost)) => {
+					let _ = substate.gasometer.record_cost(cost);
+					let _ = self.merge_succeed(substate);
+					self.trace_exit(&ExitReason::Succeed(s), cost, &out);
+					Capture::Exit((ExitReason::Succeed(s), out))
+				},
+				Err(e) => {
+					let _ = self.merge_fail(substate);
+					self.trace_exit(&ExitReason::Error(e), gas_limit, &[]);
+					Capture::Exit((ExitReason::Error(e), Vec::new()))
+				},
+			}
+		}
+
+		let code = Rc::new(code);
+		let address = context.address;
+		let mut runtime = Runtime::new(
+			code.clone(),
+			Rc::new(input),
+			context,
+			self.config,
+		);
+
+		let reason = substate.execute_frame(&mut runtime, &code, address);
+
+		let (gas_used, out) = match reason {
+			ExitReason::Succeed(_) | ExitReason::Revert(_) =>
+				(gas_limit - substate.gas(), runtime.machine().return_value()),
+			ExitReason::Error(_) | ExitReason::Fatal(_) => (gas_limit, Vec::new()),
+		};
+		self.trace_exit(&reason, gas_used, &out);
+
+		match reason {
+			ExitReason::Succeed(s) => {
+				let _ = self.merge_succeed(substate);
+				Capture::Exit((ExitReason::Succeed(s), out))
+			},
+			ExitReason::Error(e) => {
+				let _ = self.merge_fail(substate);
+				Capture::Exit((ExitReason::Error(e), Vec::new()))
+			},
+			ExitReason::Revert(e) => {
+				let _ = self.merge_revert(substate);
+				Capture::Exit((ExitReason::Revert(e), out))
+			},
+			ExitReason::Fatal(e) => {
+				self.gasometer.fail();
+				Capture::Exit((ExitReason::Fatal(e), Vec::new()))
+			},
+		}
+	}
+}
+
+impl<'backend, 'config, B: Backend> Handler for StackExecutor<'backend, 'config, B> {
+	type CreateInterrupt = Infallible;
+	type CreateFeedback = Infallible;
+	type CallInterrupt = Infallible;
+	type CallFeedback = Infallible;
+
+	fn balance(&self, address: H160) -> U256 {
+		self.state.get(&address).map(|v| v.basic.balance)
+			.unwrap_or(self.backend.basic(address).balance)
+	}
+
+	fn code_size(&self, address: H160) -> U256 {
+		U256::from(
+			self.state.get(&address).and_then(|v| v.code.as_ref().map(|c| c.len()))
+				.unwrap_or(self.backend.code_size(address))
+		)
+	}
+
+	fn code_hash(&self, address: H160) -> H256 {
+		if !self.exists(address) {
+			return H256::default()
+		}
+
+		let (balance, nonce, code_size) = if let Some(account) = self.state.get(&address) {
+			(account.basic.balance, account.basic.nonce,
+			 account.code.as_ref().map(|c| U256::from(c.len())).unwrap_or(self.code_size(address)))
+		} else {
+			let basic = self.backend.basic(address);
+			(basic.balance, basic.nonce, U256::from(self.backend.code_size(address)))
+		};
+
+		if balance == U256::zero() && nonce == U256::zero() && code_size == U256::zero() {
+			return H256::default()
+		}
+
+		let value = self.state.get(&address).and_then(|v| {
+			v.code.as_ref().map(|c| {
+				H256::from_slice(Keccak256::digest(&c).as_slice())
+			})
+		}).unwrap_or(self.backend.code_hash(address));
+		value
+	}
+
+	fn code(&self, address: H160) -> Vec<u8> {
+		self.state.get(&address).and_then(|v| {
+			v.code.clone()
+		}).unwrap_or(self.backend.code(address))
+	}
+
+	fn storage(&self, address: H160, index: H256) -> H256 {
+		self.state.get(&address)
+			.and_then(|v| {
+				let s = v.storage.get(&index).cloned();
+
+				if v.reset_storage {
+					Some(s.unwrap_or(H256::default()))
+				} else {
+					s
+				}
+
+			})
+			.unwrap_or(self.backend.storage(address, index))
+	}
+
+	fn original_storage(&self, address: H160, index: H256) -> H256 {
+		if let Some(account) = self.state.get(&address) {
+			if account.reset_storage {
+				return H256::default()
+			}
+		}
+		self.backend.storage(address, index)
+	}
+
+	fn exists(&self, address: H160) -> bool {
+		if self.config.empty_considered_exists {
+			self.state.get(&address).is_some() || self.backend.exists(address)
+		} else {
+			if let Some(account) = self.state.get(&address) {
+				account.basic.nonce != U256::zero() ||
+					account.basic.balance != U256::zero() ||
+					account.code.as_ref().map(|c| c.len() != 0).unwrap_or(false) ||
+					self.backend.code(address).len() != 0
+			} else {
+				self.backend.basic(address).nonce != U256::zero() ||
+					self.backend.basic(address).balance != U256::zero() ||
+					self.backend.code(address).len() != 0
+			}
+		}
+	}
+
+	fn gas_left(&self) -> U256 { U256::from(self.gasometer.gas()) }
+
+	fn gas_price(&self) -> U256 { self.backend.gas_price() }
+	fn origin(&self) -> H160 { self.backend.origin() }
+	fn block_hash(&self, number: U256) -> H256 { self.backend.block_hash(number) }
+	fn block_number(&self) -> U256 { self.backend.block_number() }
+	fn block_coinbase(&self) -> H160 { self.backend.block_coinbase() }
+	fn block_timestamp(&self) -> U256 { self.backend.block_timestamp() }
+	fn block_difficulty(&self) -> U256 { self.backend.block_difficulty() }
+	fn block_gas_limit(&self) -> U256 { self.backend.block_gas_limit() }
+	fn chain_id(&self) -> U256 { self.backend.chain_id() }
+
+	fn deleted(&self, address: H160) -> bool { self.deleted.contains(&address) }
+
+	fn set_storage(&mut self, address: H160, index: H256, value: H256) -> Result<(), ExitError> {
+		self.account_mut(address).storage.insert(index, value);
+
+		Ok(())
+	}
+
+	fn log(&mut self, address: H160, topics: Vec<H256>, data: Vec<u8>) -> Result<(), ExitError> {
+		self.logs.push(Log {
+			address, topics, data
+		});
+
+		Ok(())
+	}
+
+	fn mark_delete(&mut self, address: H160, target: H160) -> Result<(), ExitError> {
+		let balance = self.balance(address);
+
+		self.transfer(Transfer {
+			source: address,
+			target: target,
+			value: balance
+		})?;
+		self.account_mut(address).basic.balance = U256::zero();
+
+		self.deleted.insert(address);
+
+		Ok(())
+	}
+
+	fn create(
+		&mut self,
+		caller: H160,
+		scheme: CreateScheme,
+		value: U256,
+		init_code: Vec<u8>,
+		target_gas: Option<usize>,
+	) -> Capture<(ExitReason, Option<H160>, Vec<u8>), Self::CreateInterrupt> {
+		self.create_inner(caller, scheme, value, init_code, target_gas, true)
+	}
+
+	fn call(
+		&mut self,
+		code_address: H160,
+		transfer: Option<Transfer>,
+		input: Vec<u8>,
+		target_gas: Option<usize>,
+		is_static: bool,
+		context: Context,
+	) -> Capture<(ExitReason, Vec<u8>), Self::CallInterrupt> {
+		self.call_inner(code_address, transfer, input, target_gas, is_static, true, true, context)
+	}
+
+	fn pre_validate(
+		&mut self,
+		context: &Context,
+		opcode: Result<Opcode, ExternalOpcode>,
+		stack: &Stack
+	) -> Result<(), ExitError> {
+		let (gas_cost, memory_cost) = gasometer::opcode_cost(
+			context.address, opcode, stack, self.is_static, &self.config, self
+		)?;
+
+		self.gasometer.record_opcode(gas_cost, memory_cost)?;
+
+		Ok(())
+	}
+}
+
+/// Program counter after the opcode at `pc`, given the stack before it. The result is meaningless
+/// when the opcode fails.
+fn next_pc(pc: usize, opcode: u8, stack: &Stack) -> usize {
+	let word = |n: usize| stack.peek(n).map(|v| U256::from_big_endian(v.as_bytes())).unwrap_or_default();
+
+	match opcode {
+		// JUMP
+		0x56 => word(0).low_u64() as usize,
+		// JUMPI
+		0x57 if !word(1).is_zero() => word(0).low_u64() as usize,
+		// PUSH1 to PUSH32
+		0x60..=0x7f => pc + 1 + (opcode - 0x5f) as usize,
+		_ => pc + 1,
+	}
+}
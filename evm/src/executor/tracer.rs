@@ -0,0 +1,70 @@
+use primitive_types::{H160, U256};
+use crate::{Stack, Memory, ExitReason};
+
+/// Kind of a call frame.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CallType {
+	/// `CALL`, or the call of a transaction.
+	Call,
+	/// `CALLCODE`
+	CallCode,
+	/// `DELEGATECALL`
+	DelegateCall,
+	/// `STATICCALL`
+	StaticCall,
+	/// `CREATE`, or the creation of a transaction.
+	Create,
+	/// `CREATE2`
+	Create2,
+}
+
+/// A call frame about to be executed by a `StackExecutor`.
+pub struct Frame<'a> {
+	/// Kind of the frame.
+	pub call_type: CallType,
+	/// Address of the account making the call.
+	pub from: H160,
+	/// Address of the called account, or of the created contract.
+	pub to: H160,
+	/// Value transferred by the call.
+	pub value: U256,
+	/// Gas limit of the frame.
+	pub gas: usize,
+	/// Input data, or init code of the created contract.
+	pub input: &'a [u8],
+}
+
+/// An opcode about to be executed by a `StackExecutor`.
+pub struct Step<'a> {
+	/// Depth of the call frame, 1 for the frame of the transaction.
+	pub depth: usize,
+	/// Address of the account whose code is executed.
+	pub address: H160,
+	/// Program counter.
+	pub pc: usize,
+	/// Opcode byte.
+	pub opcode: u8,
+	/// Gas remaining before the opcode.
+	pub gas: usize,
+	/// Stack before the opcode.
+	pub stack: &'a Stack,
+	/// Memory before the opcode.
+	pub memory: &'a Memory,
+}
+
+/// Observer of the execution of a `StackExecutor` and its substates.
+pub trait Tracer {
+	/// Called before an opcode is executed.
+	fn step(&mut self, _step: &Step) {}
+
+	/// Called after the opcode of the last `step` of the same frame was executed, with the gas it
+	/// cost, including the gas used by the frames it called, and the resulting stack.
+	fn step_result(&mut self, _gas_cost: usize, _stack: &Stack) {}
+
+	/// Called when a call frame is entered.
+	fn enter(&mut self, _frame: &Frame) {}
+
+	/// Called when the last entered frame exits, with the gas it used and its output, which is the
+	/// code of the created contract for a successful create.
+	fn exit(&mut self, _reason: &ExitReason, _gas_used: usize, _output: &[u8]) {}
+}
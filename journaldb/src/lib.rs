@@ -135,9 +135,9 @@ impl str::FromStr for Algorithm {
 	fn from_str(s: &str) -> Result<Self, Self::Err> {
 		match s {
 			"archive" => Ok(Algorithm::Archive),
-			"light" => Ok(Algorithm::EarlyMerge),
-			"fast" => Ok(Algorithm::OverlayRecent),
-			"basic" => Ok(Algorithm::RefCounted),
+			"light" | "earlymerge" => Ok(Algorithm::EarlyMerge),
+			"fast" | "overlayrecent" => Ok(Algorithm::OverlayRecent),
+			"basic" | "refcounted" => Ok(Algorithm::RefCounted),
 			e => Err(format!("Invalid algorithm: {}", e)),
 		}
 	}
@@ -0,0 +1,320 @@
+use std::fs::File;
+use std::str::FromStr;
+
+use bloom_state as state;
+use ethabi::{Contract, Function, ParamType, RawLog, Token};
+use ethereum_types::{H160, U256};
+use evm::backend::Log;
+use kvdb_rocksdb::Database;
+use serde_json::Value;
+use structopt::StructOpt;
+
+// ./target/debug/bloom-evm contract call --from 0000000000000000000000000000000000000001 --to 6f3a... --value 0 --gas 100000 --gas-price 0 --abi ./erc20.json --method balanceOf --args '["0x0000000000000000000000000000000000000001"]'
+// ./target/debug/bloom-evm contract transaction ... --abi ./erc20.json --method transfer --args '["0x0000000000000000000000000000000000000002", "1000"]'
+// ./target/debug/bloom-evm contract transaction ... --method transfer --args '[...]'    (ABI stored by `contract deploy`)
+
+#[derive(Debug, StructOpt, Clone)]
+pub struct AbiOpts {
+	/// The JSON ABI file of the called contract, to encode the input data from --method and --args
+	#[structopt(long = "abi")]
+	pub abi: Option<String>,
+
+	/// The called function of the ABI
+	#[structopt(long = "method")]
+	pub method: Option<String>,
+
+	/// The arguments of the called function, as a JSON array
+	#[structopt(long = "args", default_value = "[]")]
+	pub args: String,
+}
+
+/// A function call encoded from an ABI.
+pub struct AbiCall {
+	pub contract: Contract,
+	pub function: Function,
+}
+
+impl AbiOpts {
+	/// Load the ABI and encode the input data of the called function, `None` without `--abi` and
+	/// `--method`. Without `--abi`, the ABI stored when the called contract was deployed is used.
+	pub fn encode(&self, database: &Database, to: H160) -> Option<(AbiCall, Vec<u8>)> {
+		let contract = match &self.abi {
+			Some(path) => load_contract(path),
+			None => {
+				self.method.as_ref()?;
+				stored_contract(database, to).expect("No ABI stored for the contract, --abi must be provided")
+			},
+		};
+		let method = self.method.as_ref().expect("--method must be provided with --abi");
+		let args: Vec<Value> = serde_json::from_str(&self.args).expect("--args argument must be a JSON array");
+
+		let function = contract.functions_by_name(method)
+			.unwrap_or_else(|_| panic!("Function {} not found in the ABI", method))
+			.iter()
+			.find(|f| f.inputs.len() == args.len())
+			.unwrap_or_else(|| panic!("Function {} does not take {} arguments", method, args.len()))
+			.clone();
+		let kinds: Vec<ParamType> = function.inputs.iter().map(|p| p.kind.clone()).collect();
+		let tokens = tokenize_all(&kinds, &args).unwrap_or_else(|e| panic!("Invalid arguments of {}: {}", method, e));
+		let input = function.encode_input(&tokens).expect("Arguments do not match the function");
+
+		Some((AbiCall { contract, function }, input))
+	}
+}
+
+impl AbiCall {
+	/// Decode the output of the function, as hex when it does not match the outputs of the ABI.
+	pub fn decode_output(&self, output: &[u8]) -> String {
+		match self.function.decode_output(output) {
+			Ok(tokens) => {
+				let names = self.function.outputs.iter().map(|p| p.name.as_str());
+				format!("({})", format_params(names, &tokens))
+			},
+			Err(_) => format!("0x{}", hex::encode(output)),
+		}
+	}
+
+	/// Decode a log emitted by the contract, `None` when no event of the ABI matches it.
+	pub fn decode_log(&self, log: &Log) -> Option<String> {
+		let topic = log.topics.first()?;
+		let event = self.contract.events().find(|e| !e.anonymous && e.signature() == *topic)?;
+		let decoded = event.parse_log(RawLog { topics: log.topics.clone(), data: log.data.clone() }).ok()?;
+
+		let names = decoded.params.iter().map(|p| p.name.as_str());
+		let tokens: Vec<Token> = decoded.params.iter().map(|p| p.value.clone()).collect();
+		Some(format!("{}({})", event.name, format_params(names, &tokens)))
+	}
+
+	/// Print the decoded logs, the ones no event matches as raw topics and data.
+	pub fn print_logs(&self, logs: &[Log]) {
+		for (i, log) in logs.iter().enumerate() {
+			match self.decode_log(log) {
+				Some(event) => println!("log {}: address: {:?}, {}", i, log.address, event),
+				None => println!("log {}: address: {:?}, topics: {:?}, data: 0x{}", i, log.address, log.topics, hex::encode(&log.data)),
+			}
+		}
+	}
+}
+
+/// Read a JSON ABI file, or the `abi` field of a compiler artifact.
+pub fn read_abi(path: &str) -> Value {
+	let file = File::open(path).expect("ABI file not found");
+	let mut abi: Value = serde_json::from_reader(file).expect("ABI file is not valid JSON");
+	match abi.get_mut("abi") {
+		Some(inner) => inner.take(),
+		None => abi,
+	}
+}
+
+/// Load a JSON ABI file, or the `abi` field of a compiler artifact.
+pub fn load_contract(path: &str) -> Contract {
+	parse_contract(&read_abi(path))
+}
+
+/// Parse a JSON ABI. Entries unknown to the decoder (`receive`, `error`) are skipped.
+pub fn parse_contract(abi: &Value) -> Contract {
+	let mut abi = abi.clone();
+	if let Value::Array(entries) = &mut abi {
+		entries.retain(|entry| matches!(
+			entry.get("type").and_then(Value::as_str),
+			Some("function") | Some("event") | Some("constructor") | Some("fallback")
+		));
+	}
+	Contract::load(abi.to_string().as_bytes()).expect("ABI is invalid")
+}
+
+/// Record the ABI of a deployed contract, used by later calls to it without `--abi`.
+pub fn store_abi(database: &Database, address: H160, abi: &Value) {
+	let mut transaction = database.transaction();
+	transaction.put(state::COL_ABI, address.as_bytes(), abi.to_string().as_bytes());
+	database.write(transaction).unwrap();
+}
+
+/// Get the ABI recorded when the contract was deployed.
+pub fn stored_contract(database: &Database, address: H160) -> Option<Contract> {
+	let abi = database.get(state::COL_ABI, address.as_bytes()).unwrap()?;
+	let abi: Value = serde_json::from_slice(&abi).expect("Stored ABI is invalid");
+	Some(parse_contract(&abi))
+}
+
+/// Convert the JSON values to tokens of the given types.
+pub fn tokenize_all(kinds: &[ParamType], values: &[Value]) -> Result<Vec<Token>, String> {
+	if kinds.len() != values.len() {
+		return Err(format!("expected {} values, got {}", kinds.len(), values.len()));
+	}
+	kinds.iter().zip(values).map(|(kind, value)| tokenize(kind, value)).collect()
+}
+
+/// Convert a JSON value to a token of the given type. Numbers may be given as JSON numbers or
+/// as decimal or 0x-prefixed hex strings, bytes and addresses as hex strings.
+pub fn tokenize(kind: &ParamType, value: &Value) -> Result<Token, String> {
+	let invalid = || format!("invalid {} value {}", kind, value);
+	match kind {
+		ParamType::Address => {
+			let s = value.as_str().ok_or_else(invalid)?;
+			H160::from_str(s.trim_start_matches("0x")).map(Token::Address).map_err(|_| invalid())
+		},
+		ParamType::Bytes => {
+			let s = value.as_str().ok_or_else(invalid)?;
+			hex::decode(s.trim_start_matches("0x")).map(Token::Bytes).map_err(|_| invalid())
+		},
+		ParamType::FixedBytes(len) => {
+			let s = value.as_str().ok_or_else(invalid)?;
+			let mut bytes = hex::decode(s.trim_start_matches("0x")).map_err(|_| invalid())?;
+			if bytes.len() > *len {
+				return Err(invalid());
+			}
+			bytes.resize(*len, 0);
+			Ok(Token::FixedBytes(bytes))
+		},
+		ParamType::Uint(bits) => {
+			let (negative, n) = parse_number(value).ok_or_else(invalid)?;
+			if negative || n.bits() > *bits {
+				return Err(invalid());
+			}
+			Ok(Token::Uint(n))
+		},
+		ParamType::Int(bits) => {
+			let (negative, n) = parse_number(value).ok_or_else(invalid)?;
+			let max = U256::one() << (*bits - 1);
+			match negative {
+				false if n < max => Ok(Token::Int(n)),
+				true if n <= max => Ok(Token::Int((!n).overflowing_add(U256::one()).0)),
+				_ => Err(invalid()),
+			}
+		},
+		ParamType::Bool => match value {
+			Value::Bool(b) => Ok(Token::Bool(*b)),
+			Value::String(s) => bool::from_str(s).map(Token::Bool).map_err(|_| invalid()),
+			_ => Err(invalid()),
+		},
+		ParamType::String => value.as_str().map(|s| Token::String(s.to_string())).ok_or_else(invalid),
+		ParamType::Array(inner) => {
+			let values = value.as_array().ok_or_else(invalid)?;
+			values.iter().map(|v| tokenize(inner, v)).collect::<Result<_, _>>().map(Token::Array)
+		},
+		ParamType::FixedArray(inner, len) => {
+			let values = value.as_array().filter(|values| values.len() == *len).ok_or_else(invalid)?;
+			values.iter().map(|v| tokenize(inner, v)).collect::<Result<_, _>>().map(Token::FixedArray)
+		},
+		ParamType::Tuple(kinds) => {
+			let values = value.as_array().filter(|values| values.len() == kinds.len()).ok_or_else(invalid)?;
+			kinds.iter().zip(values).map(|(k, v)| tokenize(k, v)).collect::<Result<_, _>>().map(Token::Tuple)
+		},
+	}
+}
+
+/// Parse a JSON number or a decimal or hex string into its sign and magnitude.
+fn parse_number(value: &Value) -> Option<(bool, U256)> {
+	match value {
+		Value::Number(n) => match (n.as_u64(), n.as_i64()) {
+			(Some(n), _) => Some((false, U256::from(n))),
+			(None, Some(n)) => Some((true, U256::from(n.unsigned_abs()))),
+			_ => None,
+		},
+		Value::String(s) => {
+			let (negative, s) = match s.strip_prefix('-') {
+				Some(s) => (true, s),
+				None => (false, s.as_str()),
+			};
+			let n = match s.strip_prefix("0x") {
+				Some(hex) => U256::from_str(hex).ok()?,
+				None => U256::from_dec_str(s).ok()?,
+			};
+			Some((negative, n))
+		},
+		_ => None,
+	}
+}
+
+/// Format the values as `name: value` pairs, or bare values for unnamed ones.
+fn format_params<'a>(names: impl Iterator<Item = &'a str>, tokens: &[Token]) -> String {
+	names.zip(tokens)
+		.map(|(name, token)| match name {
+			"" => format_token(token),
+			name => format!("{}: {}", name, format_token(token)),
+		})
+		.collect::<Vec<_>>()
+		.join(", ")
+}
+
+/// Format a token the way Solidity writes literals, integers in decimal.
+pub fn format_token(token: &Token) -> String {
+	match token {
+		Token::Address(a) => format!("{:?}", a),
+		Token::Bytes(b) | Token::FixedBytes(b) => format!("0x{}", hex::encode(b)),
+		Token::Uint(n) => n.to_string(),
+		Token::Int(n) => match n.bit(255) {
+			true => format!("-{}", (!*n).overflowing_add(U256::one()).0),
+			false => n.to_string(),
+		},
+		Token::Bool(b) => b.to_string(),
+		Token::String(s) => format!("{:?}", s),
+		Token::Array(tokens) | Token::FixedArray(tokens) => {
+			format!("[{}]", tokens.iter().map(format_token).collect::<Vec<_>>().join(", "))
+		},
+		Token::Tuple(tokens) => {
+			format!("({})", tokens.iter().map(format_token).collect::<Vec<_>>().join(", "))
+		},
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{format_token, tokenize, AbiCall};
+	use ethabi::{Contract, ParamType, Token};
+	use ethereum_types::{H160, H256, U256};
+	use evm::backend::Log;
+	use serde_json::json;
+
+	const ERC20: &str = r#"[
+		{"type":"function","name":"transfer","constant":false,"inputs":[{"name":"to","type":"address"},{"name":"value","type":"uint256"}],"outputs":[{"name":"","type":"bool"}]},
+		{"type":"event","name":"Transfer","anonymous":false,"inputs":[{"name":"from","type":"address","indexed":true},{"name":"to","type":"address","indexed":true},{"name":"value","type":"uint256","indexed":false}]}
+	]"#;
+
+	#[test]
+	fn test_tokenize() {
+		assert_eq!(tokenize(&ParamType::Uint(256), &json!(42)).unwrap(), Token::Uint(U256::from(42)));
+		assert_eq!(tokenize(&ParamType::Uint(256), &json!("0x2a")).unwrap(), Token::Uint(U256::from(42)));
+		assert!(tokenize(&ParamType::Uint(8), &json!(256)).is_err());
+		assert!(tokenize(&ParamType::Uint(8), &json!(-1)).is_err());
+		assert_eq!(format_token(&tokenize(&ParamType::Int(8), &json!(-128)).unwrap()), "-128");
+		assert!(tokenize(&ParamType::Int(8), &json!(128)).is_err());
+		assert_eq!(
+			tokenize(&ParamType::Array(Box::new(ParamType::Bool)), &json!([true, "false"])).unwrap(),
+			Token::Array(vec![Token::Bool(true), Token::Bool(false)]),
+		);
+		assert_eq!(tokenize(&ParamType::FixedBytes(2), &json!("0x01")).unwrap(), Token::FixedBytes(vec![1, 0]));
+	}
+
+	#[test]
+	fn test_abi_call() {
+		let contract = Contract::load(ERC20.as_bytes()).unwrap();
+		let function = contract.function("transfer").unwrap().clone();
+		let to = H160::from_low_u64_be(2);
+		let input = function.encode_input(&[Token::Address(to), Token::Uint(U256::from(1000))]).unwrap();
+		assert_eq!(hex::encode(&input[..4]), "a9059cbb");
+
+		let call = AbiCall { contract, function };
+		let mut output = [0u8; 32];
+		output[31] = 1;
+		assert_eq!(call.decode_output(&output), "(true)");
+		assert_eq!(call.decode_output(&[1]), "0x01");
+
+		let event = call.contract.event("Transfer").unwrap();
+		let mut from = H256::zero();
+		from.as_bytes_mut()[31] = 1;
+		let mut value = [0u8; 32];
+		U256::from(1000).to_big_endian(&mut value);
+		let log = Log {
+			address: H160::from_low_u64_be(9),
+			topics: vec![event.signature(), from, H256::from(to)],
+			data: value.to_vec(),
+		};
+		assert_eq!(
+			call.decode_log(&log).unwrap(),
+			"Transfer(from: 0x0000000000000000000000000000000000000001, to: 0x0000000000000000000000000000000000000002, value: 1000)",
+		);
+		assert_eq!(call.decode_log(&Log { topics: vec![H256::zero()], ..log }), None);
+	}
+}
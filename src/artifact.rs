@@ -0,0 +1,169 @@
+use std::str::FromStr;
+
+use ethereum_types::H160;
+use serde_json::Value;
+use sha3::{Digest, Keccak256};
+
+// ./target/debug/bloom-evm contract deploy ... --code-file ./artifacts/Token.json --constructor-args '["Token", 1000]'
+// ./target/debug/bloom-evm contract deploy ... --code-file ./out/Vault.json --link SafeMath=0x6f3a...
+
+/// Address of a deployed library, given as `Name=address`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Link {
+	/// Name of the library, optionally qualified by its source file as `path/File.sol:Name`.
+	pub name: String,
+	pub address: H160,
+}
+
+impl FromStr for Link {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let invalid = || format!("Invalid link {}, expected Name=address", s);
+		let mut parts = s.splitn(2, '=');
+		let name = parts.next().filter(|name| !name.is_empty()).ok_or_else(invalid)?;
+		let address = parts.next().ok_or_else(invalid)?;
+		let address = H160::from_str(address.trim_start_matches("0x")).map_err(|_| invalid())?;
+		Ok(Link { name: name.to_string(), address })
+	}
+}
+
+impl Link {
+	/// Whether the library of the placeholder is this one. Placeholders hold either the
+	/// qualified name truncated to 36 characters, or the hash of the qualified name.
+	fn matches(&self, placeholder: &str) -> bool {
+		match placeholder.strip_prefix("__$").and_then(|p| p.strip_suffix("$__")) {
+			Some(hash) => hex::encode(Keccak256::digest(self.name.as_bytes())).starts_with(hash),
+			None => {
+				let name = placeholder.trim_matches('_');
+				name == self.name || name.ends_with(&format!(":{}", self.name)) || (name.len() == 36 && self.name.starts_with(name))
+			},
+		}
+	}
+}
+
+/// Contract code read from `--code`, `--code-file` or a solc, hardhat or foundry artifact.
+pub struct Artifact {
+	/// ABI of the contract, if the code came from an artifact.
+	pub abi: Option<Value>,
+	/// Hex creation code, possibly holding library placeholders.
+	bytecode: String,
+	/// Positions of the library placeholders, `{ file: { Name: [{ start, length }] } }`.
+	link_references: Option<Value>,
+}
+
+impl Artifact {
+	/// Parse an artifact JSON, or hex code with an optional 0x prefix.
+	pub fn parse(contents: &str) -> Result<Self, String> {
+		let contents = contents.trim();
+		if !contents.starts_with('{') {
+			return Ok(Artifact {
+				abi: None,
+				bytecode: contents.trim_start_matches("0x").to_string(),
+				link_references: None,
+			});
+		}
+
+		let artifact: Value = serde_json::from_str(contents).map_err(|e| format!("Artifact is not valid JSON: {}", e))?;
+		// hardhat and truffle keep the hex at the top level, foundry in `bytecode.object` and solc in
+		// `evm.bytecode.object`, each with the placeholder positions next to it.
+		let (bytecode, link_references) = match &artifact["bytecode"] {
+			Value::String(bytecode) => (bytecode.as_str(), &artifact["linkReferences"]),
+			Value::Object(_) => (artifact["bytecode"]["object"].as_str().unwrap_or(""), &artifact["bytecode"]["linkReferences"]),
+			_ => (artifact["evm"]["bytecode"]["object"].as_str().unwrap_or(""), &artifact["evm"]["bytecode"]["linkReferences"]),
+		};
+		let bytecode = bytecode.trim().trim_start_matches("0x");
+		if bytecode.is_empty() {
+			return Err("Artifact has no creation bytecode".to_string());
+		}
+
+		Ok(Artifact {
+			abi: artifact.get("abi").cloned(),
+			bytecode: bytecode.to_string(),
+			link_references: Some(link_references.clone()).filter(Value::is_object),
+		})
+	}
+
+	/// Creation code with the library placeholders replaced by the addresses of the links.
+	pub fn code(&self, links: &[Link]) -> Result<Vec<u8>, String> {
+		let mut bytecode = self.bytecode.clone();
+
+		if let Some(Value::Object(files)) = &self.link_references {
+			for (file, libraries) in files {
+				let libraries = libraries.as_object().into_iter().flatten();
+				for (name, positions) in libraries {
+					let link = links.iter().find(|l| l.name == *name || l.name == format!("{}:{}", file, name));
+					let link = match link {
+						Some(link) => link,
+						None => continue,
+					};
+					for position in positions.as_array().into_iter().flatten() {
+						let start = position["start"].as_u64().ok_or("Invalid link reference")? as usize * 2;
+						let end = start + position["length"].as_u64().ok_or("Invalid link reference")? as usize * 2;
+						if end > bytecode.len() {
+							return Err(format!("Link reference of {} is out of the bytecode", name));
+						}
+						bytecode.replace_range(start..end, &hex::encode(link.address));
+					}
+				}
+			}
+		}
+
+		while let Some(start) = bytecode.find("__") {
+			let placeholder = bytecode.get(start..start + 40).ok_or("Truncated library placeholder")?.to_string();
+			let link = links.iter().find(|l| l.matches(&placeholder))
+				.ok_or_else(|| format!("Unresolved library placeholder {}, use --link Name=address", placeholder))?;
+			bytecode.replace_range(start..start + 40, &hex::encode(link.address));
+		}
+
+		hex::decode(&bytecode).map_err(|_| "Code is invalid".to_string())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{Artifact, Link};
+	use ethereum_types::H160;
+	use serde_json::json;
+	use std::str::FromStr;
+
+	#[test]
+	fn test_link_parsing() {
+		let link = Link::from_str("contracts/Math.sol:Math=0x0000000000000000000000000000000000000009").unwrap();
+		assert_eq!(link.name, "contracts/Math.sol:Math");
+		assert_eq!(link.address, H160::from_low_u64_be(9));
+		assert!(Link::from_str("Math").is_err());
+		assert!(Link::from_str("=0x0000000000000000000000000000000000000009").is_err());
+	}
+
+	#[test]
+	fn test_artifact_code() {
+		assert_eq!(Artifact::parse("0x6000\n").unwrap().code(&[]).unwrap(), vec![0x60, 0x00]);
+
+		let link = Link::from_str("Math=0x0000000000000000000000000000000000000009").unwrap();
+		let address = "0000000000000000000000000000000000000009";
+
+		// truffle style placeholder, no positions
+		let old = format!("73__Math{}60", "_".repeat(34));
+		let artifact = json!({ "abi": [], "bytecode": format!("0x{}", old) }).to_string();
+		let artifact = Artifact::parse(&artifact).unwrap();
+		assert_eq!(artifact.abi, Some(json!([])));
+		assert_eq!(hex::encode(artifact.code(std::slice::from_ref(&link)).unwrap()), format!("73{}60", address));
+		assert!(artifact.code(&[]).unwrap_err().starts_with("Unresolved library placeholder __Math"));
+
+		// foundry style placeholder, resolved through the positions
+		let new = "73__$aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa$__60";
+		let artifact = json!({
+			"bytecode": {
+				"object": format!("0x{}", new),
+				"linkReferences": { "src/Math.sol": { "Math": [{ "start": 1, "length": 20 }] } },
+			},
+			"deployedBytecode": { "object": "0x" },
+		}).to_string();
+		let artifact = Artifact::parse(&artifact).unwrap();
+		assert_eq!(artifact.abi, None);
+		assert_eq!(hex::encode(artifact.code(&[link]).unwrap()), format!("73{}60", address));
+
+		assert!(Artifact::parse(r#"{"bytecode": "0x"}"#).is_err());
+	}
+}
@@ -4,14 +4,21 @@ use evm::backend::{Apply,Basic};
 use evm::executor::StackExecutor;
 use evm::Config;
 use ethereum_types::{H160, H256, U256};
-use bloom_state::State;
+use bloom_state::{State, Receipt};
+use bloom_state::units::parse_amount;
 use std::collections::BTreeMap;
+use evm::backend::Backend;
 use std::str::FromStr; // !!! Necessary for H160::from_str(address).expect("...");
 
-use crate::executer;
+use crate::executer::{self, Error};
+use crate::keystore::{self, KeyFile, PasswordOpts, SignOpts};
+use crate::transaction::public_to_address;
 
 // target/debug/bloom-evm account create --address 59a5208b32e627891c389ebafc644145224006e8 --value 10 --nonce 12
+// target/debug/bloom-evm account transfer --from 59a5208b32e627891c389ebafc644145224006e8 --to 0000000000000000000000000000000000000002 --value 0.5ether --gas 21000 --gas-price 20gwei
 // target/debug/bloom-evm account query --address 59a5208b32e627891c389ebafc644145224006e8
+// target/debug/bloom-evm account new --password-file ./password
+// target/debug/bloom-evm account import --key 4646464646464646464646464646464646464646464646464646464646464646 --password-file ./password
 
 #[derive(Debug, StructOpt, Clone)]
 pub struct AccountCmd {
@@ -43,9 +50,9 @@ enum Command {
 		#[structopt(long = "address")]
 		address: String,
 
-		/// Value(Wei) for the given address,  default 1 ether (18 zeros)
-		#[structopt(long = "value", default_value = "1000000000000000000")]
-		value: String,
+		/// Value for the given address, in wei or with a unit as 1.5ether or 20gwei, default 1 ether
+		#[structopt(long = "value", default_value = "1ether", parse(try_from_str = parse_amount))]
+		value: U256,
 
 		/// Nonce for the given address, default 0
 		#[structopt(long = "nonce", default_value = "0")]
@@ -59,8 +66,8 @@ enum Command {
 		address: String,
 
 		/// Value(Wei) for the given address
-		#[structopt(long = "value")]
-		value: String,
+		#[structopt(long = "value", parse(try_from_str = parse_amount))]
+		value: U256,
 
 		/// Nonce for the given address
 		#[structopt(long = "nonce")]
@@ -71,31 +78,57 @@ enum Command {
 	Transfer{
 		/// The address from which transfer from
 		#[structopt(long = "from")]
-		from: String,
+		from: Option<String>,
 
 		/// The address from which transfer to
 		#[structopt(long = "to")]
 		to: String,
 
 		/// Value for transfer
-		#[structopt(long = "value")]
-		value: String,
+		#[structopt(long = "value", parse(try_from_str = parse_amount))]
+		value: U256,
 
 		/// The gas limit for messageCall
-		#[structopt(long = "gas")]
-		gas: u32,
+		#[structopt(long = "gas", parse(try_from_str = executer::parse_gas))]
+		gas: u64,
 
 		/// The gas price (Wei) for messageCall
-		#[structopt(long = "gas-price")]
-		gas_price: String,
+		#[structopt(long = "gas-price", parse(try_from_str = parse_amount))]
+		gas_price: U256,
+
+		/// The nonce of the transaction, default the next nonce of the sender
+		#[structopt(long = "nonce")]
+		nonce: Option<String>,
 
 		/// The input data for messageCall
 		#[structopt(long = "data")]
 		data: Option<String>,
+
+		#[structopt(flatten)]
+		sign: SignOpts,
 	},
 
 	/// List all the account
-	List{}
+	List{},
+
+	/// Create a new key in the keystore of the data directory
+	New{
+		#[structopt(flatten)]
+		password: PasswordOpts,
+	},
+
+	/// Import a private key into the keystore of the data directory
+	Import{
+		/// The private key, as hex
+		#[structopt(long = "key")]
+		key: String,
+
+		#[structopt(flatten)]
+		password: PasswordOpts,
+	},
+
+	/// List the key files of the keystore of the data directory
+	ListKeys{},
 }
 
 //#[derive(Debug)]
@@ -140,7 +173,12 @@ enum Command {
 
 
 impl AccountCmd {
-	pub fn run(&self,backend: &mut State) -> bool {
+	/// Whether the command only reads the state.
+	pub fn is_read_only(&self) -> bool {
+		matches!(self.cmd, Command::Query {..} | Command::List {})
+	}
+
+	pub fn run(&self,backend: &mut State, config: &Config, data_dir: &str, receipts: &mut Vec<Receipt>) -> Result<bool, Error> {
 		match &self.cmd {
 			Command::Query {address, storage_trie, code_hash} => {
 				let from = H160::from_str(address).expect("--address argument must be a valid address");
@@ -149,7 +187,7 @@ impl AccountCmd {
 				match (storage_trie,code_hash) {
 
 					(None, None) => {
-						println!("{:?}", account);
+						println!("{}", account);
 					},
 
 					(Some(x), None) => {
@@ -176,12 +214,12 @@ impl AccountCmd {
 
 					}
 				}
-				return false;
+				return Ok(false);
 			},
 
 			Command::Create {address,value,nonce} => {
 				let from = H160::from_str(address).expect("--address argument must be a valid address");
-				let value = U256::from_dec_str(value.as_str()).expect("--value argument must be a valid number");
+				let value = *value;
 				let nonce = U256::from_dec_str(nonce.as_str()).expect("--nonce argument must be a valid number");
 
 				let mut applies = Vec::<Apply<BTreeMap<H256, H256>>>::new();
@@ -200,12 +238,12 @@ impl AccountCmd {
 				backend.apply(applies,Vec::new(),false);
 				let account = backend.get_account(from);
 				println!("{}", account);
-				return true;
+				return Ok(true);
 			},
 
 			Command::Modify {address,value,nonce} => {
 				let from = H160::from_str(address).expect("--address argument must be a valid address");
-				let value = U256::from_dec_str(value.as_str()).expect("--value argument must be a valid number");
+				let value = *value;
 				let nonce = U256::from_dec_str(nonce.as_str()).expect("--nonce argument must be a valid number");
 
 				let mut applies = Vec::<Apply<BTreeMap<H256, H256>>>::new();
@@ -228,48 +266,54 @@ impl AccountCmd {
 				backend.apply(applies,Vec::new(),false);
 				let account = backend.get_account(from);
 				println!("{}", account);
-				return true;
+				return Ok(true);
 			},
 
-			Command::Transfer {from, to, value, gas, gas_price,data} => {
+			Command::Transfer {from, to, value, gas, gas_price,nonce,data,sign} => {
 
-				let from = H160::from_str(from).expect("--from argument must be a valid address");
+				let signer = sign.signer(from);
+				let from = signer.address();
 				let to  = H160::from_str(to).expect("--to argument must be a valid address");
-				let value = U256::from_dec_str(value.as_str()).expect("--value argument must be a valid number");
-				let gas_price = U256::from_dec_str(gas_price.as_str()).expect("Gas price is invalid");
+				let value = *value;
+				let gas_price = *gas_price;
 				let gas_limit = *gas;
+				let gas = executer::gas_usize(gas_limit)?;
 
 
 
-				let input = data.as_ref().map_or(vec![], |d| hex::decode(d.as_str()).expect("Input is invalid"));
+				let input = data.as_ref().map_or(vec![], |d| hex::decode(d.trim_start_matches("0x")).expect("Input is invalid"));
 
-				let config = Config::istanbul();
 				let executor = StackExecutor::new(
 					backend,
-					gas_limit as usize,
-					&config,
+					gas,
+					config,
 				);
-				let nonce = Some(executor.nonce(from.clone()));
+				let nonce = nonce.as_ref().map_or(executor.nonce(from), |nonce| U256::from_dec_str(nonce).expect("--nonce argument must be a valid number"));
+				let (from, hash) = signer.transaction(nonce, gas_price, gas_limit, Some(to), value, &input, backend.chain_id())?;
 
-				let retv = executer::execute_evm(
+				let executed = executer::execute_evm(
 					from.clone(),
 					value,
 					gas_limit,
 					gas_price,
-					nonce,
+					Some(nonce),
+					executer::intrinsic_gas(config, false, &input),
 					|executor| ((), executor.transact_call(
 						from,
 						to,
 						value,
 						input,
-						gas_limit as usize,
+						gas,
 					)),
+					config,
 					backend
-				).expect("Transfer failed");
+				)?;
+				super::print_gas_used(&executed);
+				executed.record(hash, None, receipts)?;
 
 				println!("Transfer Called, State OK.");
 
-				return true;
+				return Ok(true);
 			},
 
 			Command::List{} => {
@@ -282,10 +326,33 @@ impl AccountCmd {
 						println!("{:?}, external account",a);
 					}
 				}
-				return false;
-			}
+				return Ok(false);
+			},
 
+			Command::New {password} => {
+				let secret = secp256k1::SecretKey::random(&mut rand::thread_rng());
+				let path = KeyFile::encrypt(&secret, &password.password()).save(&keystore::keystore_dir(data_dir));
+				println!("{:?}, key file {}", public_to_address(&secp256k1::PublicKey::from_secret_key(&secret)), path.display());
+				Ok(false)
+			},
 
+			Command::Import {key, password} => {
+				let key = hex::decode(key.trim_start_matches("0x")).expect("--key argument must be a valid hex key");
+				let secret = secp256k1::SecretKey::parse_slice(&key).expect("--key argument must be a valid private key");
+				let path = KeyFile::encrypt(&secret, &password.password()).save(&keystore::keystore_dir(data_dir));
+				println!("{:?}, key file {}", public_to_address(&secp256k1::PublicKey::from_secret_key(&secret)), path.display());
+				Ok(false)
+			},
+
+			Command::ListKeys {} => {
+				for (path, address) in keystore::list(&keystore::keystore_dir(data_dir)) {
+					match address {
+						Some(address) => println!("0x{}, key file {}", address, path.display()),
+						None => println!("unknown address, key file {}", path.display()),
+					}
+				}
+				Ok(false)
+			},
 		}
 
 	}
@@ -0,0 +1,275 @@
+use structopt::StructOpt;
+use kvdb_rocksdb::Database;
+use bloom_state as state;
+use state::{BackendVicinity, Block, Header, Receipt};
+use ethereum_types::{U256, H256};
+use keccak_hash::KECCAK_NULL_RLP;
+
+use std::process;
+use std::str::FromStr;
+
+use super::receipt_cmd::receipts_at;
+use super::root_at;
+
+// ./target/debug/bloom-evm block mine
+// ./target/debug/bloom-evm block show 3
+// ./target/debug/bloom-evm block show 0x5c3e...
+
+/// Number of recent block hashes readable by BLOCKHASH.
+const BLOCK_HASHES: usize = 256;
+
+#[derive(Debug, StructOpt, Clone)]
+pub struct BlockCmd {
+    #[structopt(subcommand)]
+    cmd: Command
+}
+
+#[derive(StructOpt, Debug, Clone)]
+enum Command {
+    /// Seal the transactions committed since the latest block into a new block
+    Mine {},
+
+    /// Show a block
+    Show {
+        /// The number or the hash of the block, default the latest block
+        block: Option<String>,
+    },
+}
+
+impl BlockCmd {
+    pub fn run(&self, db: &Database, count: U256, vicinity: &BackendVicinity) -> bool {
+        match &self.cmd {
+            Command::Mine {} => {
+                let block = mine(db, count, vicinity);
+                println!("Mined block {} {:?} with {} transactions", block.header.number, block.header.hash(), block.transactions.len());
+            },
+
+            Command::Show {block} => {
+                let number = match block {
+                    None => latest_block(db).map(|block| block.header.number),
+                    Some(block) if block.starts_with("0x") || block.len() == 64 => {
+                        let hash = H256::from_str(block.trim_start_matches("0x")).expect("Block hash is invalid");
+                        db.get(state::COL_HEADER, hash.as_bytes()).unwrap().map(|v| U256::from(v.as_slice()))
+                    },
+                    Some(block) => Some(U256::from_dec_str(block).expect("Block number is invalid")),
+                };
+
+                match number.and_then(|number| block_at(db, number)) {
+                    Some(block) => {
+                        println!("{}", block.header);
+                        println!("commit: {}", block.commit);
+                        println!("transactions: {}", block.transactions.len());
+                        for hash in &block.transactions {
+                            println!("  {:?}", hash);
+                        }
+                    },
+                    None => {
+                        eprintln!("Block not found");
+                        process::exit(1);
+                    },
+                }
+            },
+        }
+        false
+    }
+}
+
+/// Seal the transactions committed since the latest block, up to the commit `count`, into a new
+/// block and store it as the latest block.
+///
+/// The block timestamp is the one its transactions were executed with, or the one of the vicinity
+/// for a block without transactions.
+pub fn mine(db: &Database, count: U256, vicinity: &BackendVicinity) -> Block {
+    let parent = latest_block(db);
+    let receipts = receipts_between(db, parent.as_ref().map_or(U256::zero(), |parent| parent.commit), count);
+
+    let timestamp = pending_timestamp(db).unwrap_or(vicinity.block_timestamp);
+    let state_root = match count.is_zero() {
+        true => KECCAK_NULL_RLP,
+        false => root_at(db, count.as_u64()),
+    };
+    let block = Block {
+        header: Header::new(parent.as_ref().map(|parent| &parent.header), timestamp, state_root, &receipts),
+        transactions: receipts.iter().map(|r| r.transaction_hash).collect(),
+        commit: count,
+    };
+
+    let mut number = [0u8;32];
+    block.header.number.to_big_endian(&mut number);
+    let mut transaction = db.transaction();
+    transaction.put(state::COL_HEADER, &number[..], &rlp::encode(&block));
+    transaction.put(state::COL_HEADER, block.header.hash().as_bytes(), &number[..]);
+    transaction.put(state::COL_HEADER, b"latest", &number[..]);
+    transaction.delete(state::COL_HEADER, b"pending-timestamp");
+    db.write(transaction).unwrap();
+    block
+}
+
+/// Timestamp the transactions committed since the latest block were executed with, `None` while
+/// there are none.
+pub fn pending_timestamp(db: &Database) -> Option<U256> {
+    db.get(state::COL_HEADER, b"pending-timestamp").unwrap().map(|v| U256::from(v.as_slice()))
+}
+
+/// Receipts of the transactions committed after the commit `after`, up to the commit `last`.
+///
+/// Their cumulative gas runs across all of them, as in the block sealing these commits, while the
+/// stored receipts count from the start of their own commit.
+pub fn receipts_between(db: &Database, after: U256, last: U256) -> Vec<Receipt> {
+    let mut receipts: Vec<Receipt> = Vec::new();
+    let mut commit = after + 1;
+    while commit <= last {
+        for mut receipt in receipts_at(db, commit) {
+            receipt.cumulative_gas_used = receipts.last().map_or(U256::zero(), |r| r.cumulative_gas_used) + receipt.gas_used;
+            receipts.push(receipt);
+        }
+        commit = commit + 1;
+    }
+    receipts
+}
+
+/// Receipts of the transactions of the block, in their order in the block.
+pub fn block_receipts(db: &Database, block: &Block) -> Vec<Receipt> {
+    let after = match block.header.number.is_zero() {
+        true => U256::zero(),
+        false => block_at(db, block.header.number - 1).expect("Parent block not found").commit,
+    };
+    receipts_between(db, after, block.commit)
+}
+
+/// Get the block holding the transactions of the commit, `None` while they are not mined.
+pub fn block_of_commit(db: &Database, commit: U256) -> Option<Block> {
+    let mut block = latest_block(db).filter(|block| block.commit >= commit)?;
+    while !block.header.number.is_zero() {
+        match block_at(db, block.header.number - 1) {
+            Some(parent) if parent.commit >= commit => block = parent,
+            _ => break,
+        }
+    }
+    Some(block)
+}
+
+/// Get the block with the given number.
+pub fn block_at(db: &Database, number: U256) -> Option<Block> {
+    let mut arr = [0u8;32];
+    number.to_big_endian(&mut arr);
+    db.get(state::COL_HEADER, &arr[..]).unwrap()
+        .map(|v| rlp::decode(&v).expect("Stored block is invalid"))
+}
+
+/// Get the latest sealed block, if any.
+pub fn latest_block(db: &Database) -> Option<Block> {
+    let number = db.get(state::COL_HEADER, b"latest").unwrap()?;
+    block_at(db, U256::from(number.as_slice()))
+}
+
+/// Number of the block pending after the latest sealed block.
+pub fn pending_number(db: &Database) -> U256 {
+    latest_block(db).map_or(U256::zero(), |block| block.header.number + 1)
+}
+
+/// Hashes of the blocks before the block `number`, the most recent first, as read by BLOCKHASH.
+pub fn block_hashes(db: &Database, number: U256) -> Vec<H256> {
+    let mut hashes = Vec::new();
+    let mut number = number;
+    while !number.is_zero() && hashes.len() < BLOCK_HASHES {
+        number = number - 1;
+        match block_at(db, number) {
+            Some(block) => hashes.push(block.header.hash()),
+            None => break,
+        }
+    }
+    hashes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{block_at, block_hashes, block_receipts, mine};
+    use bloom_state::{ordered_trie_root, BackendVicinity, Receipt};
+    use ethereum_types::{H160, H256, U256};
+    use evm::backend::ApplyBackend;
+    use evm::executor::StackExecutor;
+    use evm::Config;
+    use journaldb::Algorithm;
+    use structopt::StructOpt;
+
+    use crate::Cli;
+    use crate::test_helpers::{account, TempDatabase};
+
+    #[test]
+    fn test_block_hashes() {
+        let database = TempDatabase::new("block-hashes");
+        let vicinity = BackendVicinity::default();
+        let hashes: Vec<H256> = (0..3).map(|_| mine(&database, U256::zero(), &vicinity).header.hash()).collect();
+        assert_eq!(block_hashes(&database, U256::from(3)), vec![hashes[2], hashes[1], hashes[0]]);
+        assert_eq!(block_hashes(&database, U256::from(1)), vec![hashes[0]]);
+        assert!(block_hashes(&database, U256::zero()).is_empty());
+
+        // The pending block reads the hashes of the mined blocks with BLOCKHASH.
+        let cli = Cli::from_iter(&["bloom-evm"]);
+        let mut vicinity = cli.vicinity.vicinity();
+        super::super::pending_block(&cli, &database, &mut vicinity);
+        assert_eq!(vicinity.block_number, U256::from(3));
+        let mut backend = super::super::open_state(&database, Algorithm::Archive, H256::zero(), &vicinity).unwrap();
+        let contract = H160::from_low_u64_be(0xa);
+        // Returns BLOCKHASH of the number given as input.
+        let code = hex::decode("6000354060005260206000f3").unwrap();
+        backend.apply(vec![account(contract, 0, Some(code), &[])], Vec::new(), false);
+        let config = Config::istanbul();
+        let mut executor = StackExecutor::new(&backend, 100000, &config);
+        let mut blockhash = |number: u64| {
+            let mut input = [0u8; 32];
+            U256::from(number).to_big_endian(&mut input);
+            let (_, output) = executor.transact_call(H160::zero(), contract, U256::zero(), input.to_vec(), 100000);
+            H256::from_slice(&output)
+        };
+        assert_eq!(blockhash(1), hashes[1]);
+        assert_eq!(blockhash(2), hashes[2]);
+        assert_eq!(blockhash(3), H256::zero());
+    }
+
+    #[test]
+    fn test_mine_timestamp() {
+        let database = TempDatabase::new("mine-timestamp");
+        let cli = Cli::from_iter(&["bloom-evm", "--timestamp", "1000"]);
+        let mut vicinity = cli.vicinity.vicinity();
+        super::super::pending_block(&cli, &database, &mut vicinity);
+        let mut backend = super::super::open_state(&database, Algorithm::Archive, H256::zero(), &vicinity).unwrap();
+        let receipt = Receipt::new(H256::repeat_byte(1), true, U256::from(21000), U256::from(21000), None, Vec::new());
+        super::super::commit(&database, Algorithm::Archive, &mut backend, U256::zero(), &[receipt]);
+
+        // The block is sealed later with the timestamp its transaction was executed with, which
+        // later transactions of the same block see too.
+        let cli = Cli::from_iter(&["bloom-evm"]);
+        let mut vicinity = cli.vicinity.vicinity();
+        super::super::pending_block(&cli, &database, &mut vicinity);
+        assert_eq!(vicinity.block_timestamp, U256::from(1000));
+        let block = mine(&database, U256::one(), &BackendVicinity { block_timestamp: U256::from(2000), ..Default::default() });
+        assert_eq!(block.header.timestamp, U256::from(1000));
+        assert_eq!(block.transactions, vec![H256::repeat_byte(1)]);
+
+        // A block without transactions takes the timestamp of the vicinity.
+        let block = mine(&database, U256::one(), &BackendVicinity { block_timestamp: U256::from(3000), ..Default::default() });
+        assert_eq!(block.header.timestamp, U256::from(3000));
+        assert_eq!(block_at(&database, U256::one()).unwrap().header.timestamp, U256::from(3000));
+    }
+
+    #[test]
+    fn test_block_cumulative_gas() {
+        let database = TempDatabase::new("block-cumulative-gas");
+        let vicinity = BackendVicinity::default();
+        let mut backend = super::super::open_state(&database, Algorithm::Archive, H256::zero(), &vicinity).unwrap();
+        for (n, gas) in [(1u8, 21000u64), (2, 30000)].iter() {
+            let receipt = Receipt::new(H256::repeat_byte(*n), true, U256::from(*gas), U256::from(*gas), None, Vec::new());
+            super::super::commit(&database, Algorithm::Archive, &mut backend, U256::from(*n - 1), &[receipt]);
+        }
+
+        // The two commits are sealed in one block, whose receipts count the gas of both.
+        let block = mine(&database, U256::from(2), &vicinity);
+        let receipts = block_receipts(&database, &block);
+        let cumulative: Vec<U256> = receipts.iter().map(|r| r.cumulative_gas_used).collect();
+        assert_eq!(cumulative, vec![U256::from(21000), U256::from(51000)]);
+        assert_eq!(block.header.gas_used, U256::from(51000));
+        assert_eq!(block.header.receipts_root, ordered_trie_root(receipts.iter().map(rlp::encode)));
+    }
+}
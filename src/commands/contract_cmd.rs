@@ -1,21 +1,28 @@
-use crate::executer;
-use ethereum_types::{H160,U256};
+use crate::abi::{self, AbiOpts};
+use crate::artifact::{Artifact, Link};
+use crate::keystore::SignOpts;
+use crate::executer::{self, Error};
+use crate::tracer::TraceOpts;
+use ethereum_types::{H160,H256,U256};
+use evm::backend::Backend;
 use evm::executor::StackExecutor;
 use evm::Handler;
-use evm::Context;
-use evm::Capture;
-use evm::ExitReason;
 use hex;
-use bloom_state::State;
+use bloom_state::{State, Receipt};
+use bloom_state::units::parse_amount;
+use kvdb_rocksdb::Database;
 use structopt::StructOpt;
 use evm::Config;
 use std::fs::File;
 use std::io::Read;
+use sha3::{Digest, Keccak256};
 use std::str::FromStr; // !!! Necessary for H160::from_str(address).expect("...");
 
 // ./target/debug/bloom-evm contract --from 0000000000000000000000000000000000000001 --to 0000000000000000000000000000000000000002 --value 0 --gas_limit 100000 --gas-price 0 --input 6000
 // ./target/debug/bloom-evm contract deploy --from 0000000000000000000000000000000000000001  --value 0 --gas 100000 --gas-price 0 --code-file ./code-file
 // ./target/debug/bloom-evm contract deploy --from 0000000000000000000000000000000000000001  --value 0 --gas 100000 --gas-price 0 --code 000000
+// ./target/debug/bloom-evm contract deploy --from 0000000000000000000000000000000000000001  --value 0 --gas 100000 --gas-price 0 --code 000000 --salt 0x01
+// ./target/debug/bloom-evm contract deploy --from 0000000000000000000000000000000000000001  --value 0 --gas 1000000 --gas-price 0 --code-file ./artifacts/Token.json --constructor-args '["Token", 1000]' --link SafeMath=0x6f3a...
 
 #[derive(Debug, StructOpt, Clone)]
 pub struct ContractCmd {
@@ -32,25 +39,48 @@ enum Command {
         from: String,
 
         /// The value to deposit in contract
-        #[structopt(long = "value")]
-        value: String,
+        #[structopt(long = "value", parse(try_from_str = parse_amount))]
+        value: U256,
 
         /// The gas limit for deploying contract
-        #[structopt(long = "gas")]
-        gas: u32,
+        #[structopt(long = "gas", parse(try_from_str = executer::parse_gas))]
+        gas: u64,
 
         /// The gas price for deploying contract(Wei)
-        #[structopt(long = "gas-price")]
-        gas_price: String,
+        #[structopt(long = "gas-price", parse(try_from_str = parse_amount))]
+        gas_price: U256,
+
+        /// The nonce of the transaction, default the next nonce of the sender
+        #[structopt(long = "nonce")]
+        nonce: Option<String>,
 
         /// The contract binary code
         #[structopt(long = "code")]
         code: Option<String>,
 
-        /// The code file
+        /// The code file, hex or a solc, hardhat or foundry artifact JSON
         #[structopt(long = "code-file")]
         code_file: Option<String>,
 
+        /// The JSON ABI file of the contract, when the code is not an artifact
+        #[structopt(long = "abi")]
+        abi: Option<String>,
+
+        /// The constructor arguments, as a JSON array encoded with the ABI
+        #[structopt(long = "constructor-args")]
+        constructor_args: Option<String>,
+
+        /// The address of a library linked into the code, as Name=address
+        #[structopt(long = "link", number_of_values = 1)]
+        link: Vec<Link>,
+
+        /// Deploy with CREATE2 and this salt (hex, up to 32 bytes), at an address derived from it
+        #[structopt(long = "salt")]
+        salt: Option<String>,
+
+        #[structopt(flatten)]
+        trace: TraceOpts,
+
     },
 
     /// Message call
@@ -60,20 +90,20 @@ enum Command {
         from: String,
 
         /// The value (Wei) for messageCall
-        #[structopt(long = "value")]
-        value: String,
+        #[structopt(long = "value", parse(try_from_str = parse_amount))]
+        value: U256,
 
         /// The receiver address for messageCall
         #[structopt(long = "to")]
         to: String,
 
         /// The gas limit for messageCall
-        #[structopt(long = "gas")]
-        gas: u32,
+        #[structopt(long = "gas", parse(try_from_str = executer::parse_gas))]
+        gas: u64,
 
         /// The gas price (Wei) for messageCall
-        #[structopt(long = "gas-price")]
-        gas_price: String,
+        #[structopt(long = "gas-price", parse(try_from_str = parse_amount))]
+        gas_price: U256,
 
         /// The input data for messageCall
         #[structopt(long = "data")]
@@ -83,29 +113,70 @@ enum Command {
         #[structopt(long = "data-file")]
         data_file: Option<String>,
 
+        #[structopt(flatten)]
+        abi: AbiOpts,
+
+        #[structopt(flatten)]
+        trace: TraceOpts,
+
+    },
+
+    /// Estimate the gas limit needed by a deployment or a transaction, without committing it
+    EstimateGas {
+        /// The address which sends the transaction
+        #[structopt(long = "from")]
+        from: String,
+
+        /// The value (Wei) for the transaction
+        #[structopt(long = "value", default_value = "0", parse(try_from_str = parse_amount))]
+        value: U256,
+
+        /// The receiver address, deploy the input data as contract code if absent
+        #[structopt(long = "to")]
+        to: Option<String>,
+
+        /// The highest gas limit to try
+        #[structopt(long = "gas", default_value = "10000000", parse(try_from_str = executer::parse_gas))]
+        gas: u64,
+
+        /// The gas price (Wei) for the transaction
+        #[structopt(long = "gas-price", default_value = "0", parse(try_from_str = parse_amount))]
+        gas_price: U256,
+
+        /// The input data, or contract binary code when deploying
+        #[structopt(long = "data")]
+        data: Option<String>,
+
+        /// The input data file
+        #[structopt(long = "data-file")]
+        data_file: Option<String>,
     },
 
     /// Transaction call
     Transaction {
         /// The address which send messageCall
         #[structopt(long = "from")]
-        from: String,
+        from: Option<String>,
 
         /// The value (Wei) for messageCall
-        #[structopt(long = "value")]
-        value: String,
+        #[structopt(long = "value", parse(try_from_str = parse_amount))]
+        value: U256,
 
         /// The receiver address for messageCall
         #[structopt(long = "to")]
         to: String,
 
         /// The gas limit for messageCall
-        #[structopt(long = "gas")]
-        gas: u32,
+        #[structopt(long = "gas", parse(try_from_str = executer::parse_gas))]
+        gas: u64,
 
         /// The gas price (Wei) for messageCall
-        #[structopt(long = "gas-price")]
-        gas_price: String,
+        #[structopt(long = "gas-price", parse(try_from_str = parse_amount))]
+        gas_price: U256,
+
+        /// The nonce of the transaction, default the next nonce of the sender
+        #[structopt(long = "nonce")]
+        nonce: Option<String>,
 
         /// The input data for messageCall
         #[structopt(long = "data")]
@@ -115,20 +186,35 @@ enum Command {
         #[structopt(long = "data-file")]
         data_file: Option<String>,
 
+        #[structopt(flatten)]
+        sign: SignOpts,
+
+        #[structopt(flatten)]
+        abi: AbiOpts,
+
+        #[structopt(flatten)]
+        trace: TraceOpts,
+
     }
 }
 
 
 
 impl ContractCmd {
-    pub fn run(&self, backend: &mut State) -> bool{
+    /// Whether the command only reads the state.
+    pub fn is_read_only(&self) -> bool {
+        matches!(self.cmd, Command::Call {..})
+    }
+
+    pub fn run(&self, backend: &mut State, config: &Config, database: &Database, receipts: &mut Vec<Receipt>) -> Result<bool, Error> {
         match &self.cmd {
-            Command::Deploy {from,value,gas,gas_price,code,code_file} => {
+            Command::Deploy {from,value,gas,gas_price,nonce,code,code_file,abi,constructor_args,link,salt,trace} => {
 
                 let from = H160::from_str(from).expect("From should be a valid address");
-                let value = U256::from_dec_str(value.as_str()).expect("Value is invalid");
-                let gas_price = U256::from_dec_str(gas_price.as_str()).expect("Gas price is invalid");
+                let value = *value;
+                let gas_price = *gas_price;
                 let gas_limit = *gas;
+                let gas = executer::gas_usize(gas_limit)?;
 
                 let mut contents = String::new();
 
@@ -154,173 +240,327 @@ impl ContractCmd {
                     }
                 }.expect("--code or --code-file must be provided one of them ");
 
-                let code = hex::decode(code.as_str()).expect("Code is invalid");
-                let config = Config::istanbul();
+                let artifact = Artifact::parse(code).unwrap_or_else(|e| panic!("{}", e));
+                let mut code = artifact.code(link).unwrap_or_else(|e| panic!("{}", e));
+                let abi = match abi {
+                    Some(path) => Some(abi::read_abi(path)),
+                    None => artifact.abi,
+                };
+                if let Some(args) = constructor_args {
+                    let args: Vec<serde_json::Value> = serde_json::from_str(args).expect("--constructor-args argument must be a JSON array");
+                    let contract = abi::parse_contract(abi.as_ref().expect("--constructor-args needs an artifact with an ABI or --abi"));
+                    code = match contract.constructor() {
+                        Some(constructor) => {
+                            let kinds: Vec<_> = constructor.inputs.iter().map(|p| p.kind.clone()).collect();
+                            let tokens = abi::tokenize_all(&kinds, &args).unwrap_or_else(|e| panic!("Invalid constructor arguments: {}", e));
+                            constructor.encode_input(code, &tokens).expect("Arguments do not match the constructor")
+                        },
+                        None if args.is_empty() => code,
+                        None => panic!("The ABI has no constructor taking arguments"),
+                    };
+                }
                 let executor = StackExecutor::new(
                     backend,
-                    gas_limit as usize,
-                    &config,
+                    gas,
+                    config,
                 );
-                let nonce = Some(executor.nonce(from.clone()));
+                let nonce = nonce.as_ref().map_or(executor.nonce(from), |nonce| U256::from_dec_str(nonce).expect("Nonce is invalid"));
+                if salt.is_some() && !config.has_create2 {
+                    return Err(Error::Create2Unsupported);
+                }
+                let salt = salt.as_deref().map(parse_salt);
+                let scheme = create_scheme(from, salt, &code);
+                let contract_address = executor.create_address(scheme);
+                if salt.is_some() {
+                    println!("CREATE2 contract address is {:?}", contract_address);
+                    if executor.nonce(contract_address) != U256::zero() || !executor.code(contract_address).is_empty() {
+                        return Err(Error::AddressCollision(contract_address));
+                    }
+                }
+                let hash = executer::transaction_hash(from, nonce, gas_price, gas_limit, None, value, &code);
+                let logger = trace.struct_logger();
 
-                let contract_address = executer::execute_evm(
+                let (executed, calls) = executer::execute_evm_with_calls(
+                    trace.calls(),
                     from.clone(),
                     value,
                     gas_limit,
                     gas_price,
-                    nonce,
+                    Some(nonce),
+                    executer::intrinsic_gas(config, true, &code),
                     |executor| {
-                        (executor.create_address(
-                            evm::CreateScheme::Legacy { caller: from.clone() },
-                        ), executor.transact_create(
-                            from,
-                            value,
-                            code,
-                            gas_limit as usize,
-                        ))
+                        if let Some(logger) = &logger {
+                            executor.set_tracer(logger.clone());
+                        }
+                        (contract_address, match salt {
+                            Some(salt) => executor.transact_create2(
+                                from,
+                                value,
+                                code,
+                                salt,
+                                gas,
+                            ),
+                            None => executor.transact_create(
+                                from,
+                                value,
+                                code,
+                                gas,
+                            ),
+                        })
                     },
+                    config,
                     backend
-                ).expect("Create contract failed");
+                )?;
+
+                let result = executed.result.as_ref().map(|_| &[][..]);
+                trace.write_transaction(logger, calls, executed.gas_used, result);
+                super::print_gas_used(&executed);
+                let contract_address = executed.record(hash, Some(contract_address), receipts)?;
+                if let Some(abi) = &abi {
+                    abi::store_abi(database, contract_address, abi);
+                }
 
                 println!("Create contract successful, contract address is {:?}", contract_address);
-                return true;
+                return Ok(true);
             }
 
-            Command::Transaction {from,value,to,gas,gas_price,data,data_file} => {
-                let from = H160::from_str(from).expect("From should be a valid address");
+            Command::Transaction {from,value,to,gas,gas_price,nonce,data,data_file,abi,sign,trace} => {
+                let signer = sign.signer(from);
+                let from = signer.address();
                 let to = H160::from_str(to).expect("To should be a valid address");
-                let value = U256::from_dec_str(value.as_str()).expect("Value is invalid");
-                let gas_price = U256::from_dec_str(gas_price.as_str()).expect("Gas price is invalid");
+                let value = *value;
+                let gas_price = *gas_price;
                 let gas_limit = *gas;
+                let gas = executer::gas_usize(gas_limit)?;
 
-                let mut contents = String::new();
-
-                let data = match data {
-                    Some(d) => {
-                        Ok(d)
-                    }
-                    None => {
-                        let ret = match data_file {
-                            Some(file) => {
-                                let mut f = File::open(file).expect(" data file not found");
-
-                                f.read_to_string(&mut contents)
-                                    .expect("something went wrong reading the file");
-                                Ok(&contents)
-                            }
-
-                            None => {
-                                Err(())
-                            }
-                        };
-                        ret
-                    }
-                }.unwrap_or(&contents);
-
-                let input = hex::decode(data.as_str()).expect("Input is invalid");
-                let config = Config::istanbul();
+                let (call, input) = match abi.encode(database, to) {
+                    Some((call, input)) => (Some(call), input),
+                    None => (None, read_input(data, data_file)),
+                };
                 let executor = StackExecutor::new(
                     backend,
-                    gas_limit as usize,
-                    &config,
+                    gas,
+                    config,
                 );
-                let nonce = Some(executor.nonce(from.clone()));
+                let nonce = nonce.as_ref().map_or(executor.nonce(from), |nonce| U256::from_dec_str(nonce).expect("Nonce is invalid"));
+                let (from, hash) = signer.transaction(nonce, gas_price, gas_limit, Some(to), value, &input, backend.chain_id())?;
+                let logger = trace.struct_logger();
 
-                let retv = executer::execute_evm(
+                let (executed, calls) = executer::execute_evm_with_calls(
+                    trace.calls(),
                     from.clone(),
                     value,
                     gas_limit,
                     gas_price,
-                    nonce,
-                    |executor| ((), executor.transact_call(
-                        from,
-                        to,
-                        value,
-                        input,
-                        gas_limit as usize,
-                    )),
+                    Some(nonce),
+                    executer::intrinsic_gas(config, false, &input),
+                    |executor| {
+                        if let Some(logger) = &logger {
+                            executor.set_tracer(logger.clone());
+                        }
+                        let (reason, output) = executor.transact_call(
+                            from,
+                            to,
+                            value,
+                            input,
+                            gas,
+                        );
+                        (output.clone(), (reason, output))
+                    },
+                    config,
                     backend
-                ).expect("Call message failed");
+                )?;
+
+                let result = executed.result.as_ref().map(|output| output.as_slice());
+                trace.write_transaction(logger, calls, executed.gas_used, result);
+                if let Some(call) = &call {
+                    call.print_logs(&executed.logs);
+                }
+                super::print_gas_used(&executed);
+                let output = executed.record(hash, None, receipts)?;
+
+                match call {
+                    Some(call) => println!("Contract Called, State OK. result: {}", call.decode_output(&output)),
+                    None => println!("Contract Called, State OK."),
+                }
+                return Ok(true);
+            }
 
-                println!("Contract Called, State OK.");
-                return true;
+            Command::EstimateGas {from,value,to,gas,gas_price,data,data_file} => {
+                let from = H160::from_str(from).expect("From should be a valid address");
+                let to = to.as_ref().map(|to| H160::from_str(to).expect("To should be a valid address"));
+                let value = *value;
+                let gas_price = *gas_price;
+
+                let input = read_input(data, data_file);
+
+                let intrinsic_gas = executer::intrinsic_gas(config, to.is_none(), &input);
+                let (gas_limit, gas_used) = executer::estimate_gas(*gas, |gas_limit| {
+                    let gas = executer::gas_usize(gas_limit)?;
+                    let mut state = backend.clone();
+                    let input = input.clone();
+                    executer::execute_evm(
+                        from,
+                        value,
+                        gas_limit,
+                        gas_price,
+                        None,
+                        intrinsic_gas,
+                        |executor| ((), match to {
+                            Some(to) => executor.transact_call(from, to, value, input, gas),
+                            None => executor.transact_create(from, value, input, gas),
+                        }),
+                        config,
+                        &mut state
+                    )
+                })?;
+
+                println!("Estimated gas limit: {}, gas used: {} (intrinsic: {}, execution: {})", gas_limit, gas_used, intrinsic_gas, gas_used.saturating_sub(intrinsic_gas.into()));
+                return Ok(false);
             }
 
-            Command::Call {from,value,to,gas,gas_price,data,data_file} => {
+            Command::Call {from,value,to,gas,gas_price,data,data_file,abi,trace} => {
                 let from = H160::from_str(from).expect("From should be a valid address");
                 let to = H160::from_str(to).expect("To should be a valid address");
-                let value = U256::from_dec_str(value.as_str()).expect("Value is invalid");
-                let gas_price = U256::from_dec_str(gas_price.as_str()).expect("Gas price is invalid");
+                let value = *value;
+                let gas_price = *gas_price;
                 let gas_limit = *gas;
+                let gas = executer::gas_usize(gas_limit)?;
 
-                let mut contents = String::new();
-
-                let data = match data {
-                    Some(d) => {
-                        Ok(d)
-                    }
+                let (call, input) = match abi.encode(database, to) {
+                    Some((call, input)) => (Some(call), input),
+                    None => (None, read_input(data, data_file)),
+                };
+                // The call pays its fee like a transaction, but the state is not committed.
+                let logger = trace.struct_logger();
+                let (executed, calls) = executer::execute_evm_with_calls(
+                    trace.calls(),
+                    from,
+                    value,
+                    gas_limit,
+                    gas_price,
+                    None,
+                    executer::intrinsic_gas(config, false, &input),
+                    |executor| {
+                        if let Some(logger) = &logger {
+                            executor.set_tracer(logger.clone());
+                        }
+                        let (reason, output) = executor.transact_call(from, to, value, input, gas);
+                        (output.clone(), (reason, output))
+                    },
+                    config,
+                    backend
+                )?;
+
+                let retv = executed.result;
+                let result = retv.as_ref().map(|output| output.as_slice());
+                trace.write_transaction(logger, calls, executed.gas_used, result);
+                let retv = retv?;
+                match call {
+                    Some(call) => println!("Contract Message Called, State OK. result: {}", call.decode_output(&retv)),
                     None => {
-                        let ret = match data_file {
-                            Some(file) => {
-                                let mut f = File::open(file).expect(" data file not found");
+                        let r = hex::encode(retv);
+                        println!("Contract Message Called, State OK. result: {:?}",r);
+                    },
+                }
 
-                                f.read_to_string(&mut contents)
-                                    .expect("something went wrong reading the file");
-                                Ok(&contents)
-                            }
+                return Ok(false);
+            }
+        }
+    }
+}
 
-                            None => {
-                                Err(())
-                            }
-                        };
-                        ret
-                    }
-                }.unwrap_or(&contents);
+/// Input of a call, hex from --data or --data-file with an optional 0x prefix, empty if neither is given.
+fn read_input(data: &Option<String>, data_file: &Option<String>) -> Vec<u8> {
+    let contents = match (data, data_file) {
+        (Some(d), _) => d.clone(),
+        (None, Some(file)) => {
+            let mut contents = String::new();
+            let mut f = File::open(file).expect(" data file not found");
+            f.read_to_string(&mut contents)
+                .expect("something went wrong reading the file");
+            contents
+        },
+        (None, None) => String::new(),
+    };
+    parse_input(&contents)
+}
 
-                let input = hex::decode(data.as_str()).expect("Input is invalid");
-                let config = Config::istanbul();
-                let mut executor = StackExecutor::new(
-                    backend,
-                    gas_limit as usize,
-                    &config,
-                );
-                let context = Context {
-                    caller: from.clone(),
-                    address: to.clone(),
-                    apparent_value: value,
-                };
-                let retv =  executor.call(
-                        to,
-                        None,
-                        input,
-                        None,
-                        true,
-                        context);
+/// Decode hex input, ignoring surrounding whitespace and a 0x prefix.
+fn parse_input(input: &str) -> Vec<u8> {
+    hex::decode(input.trim().trim_start_matches("0x")).expect("Input is invalid")
+}
 
-                let (reason,retv) = match retv {
-                    Capture::Exit(s) => s,
-                    Capture::Trap(_) => unreachable!(),
-                };
+/// Parse a salt of up to 32 bytes, as hex, left padded with zeros.
+fn parse_salt(salt: &str) -> H256 {
+    let salt = hex::decode(salt.trim_start_matches("0x")).ok().filter(|s| s.len() <= 32).expect("Salt is invalid");
+    let mut bytes = [0u8; 32];
+    bytes[32 - salt.len()..].copy_from_slice(&salt);
+    H256::from(bytes)
+}
 
-                match reason {
-                    ExitReason::Succeed(_) => {
-                        let r = hex::encode(retv);
-                        println!("Contract Message Called, State OK. result: {:?}",r);
-                    }
-                    ExitReason::Error(e) => {
-                        println!("Contract message call encounter error. {:?}",e);
+/// Scheme of a contract deployed by `from` with the init code, CREATE2 when a salt is given.
+fn create_scheme(from: H160, salt: Option<H256>, code: &[u8]) -> evm::CreateScheme {
+    match salt {
+        Some(salt) => evm::CreateScheme::Create2 {
+            caller: from,
+            code_hash: H256::from_slice(Keccak256::digest(code).as_slice()),
+            salt,
+        },
+        None => evm::CreateScheme::Legacy { caller: from },
+    }
+}
 
-                    }
-                    ExitReason::Revert(e) => {
-                        println!("Contract message call encounter error. {:?}",e);
-                    },
-                    ExitReason::Fatal(e) => {
-                        println!("Contract message call encounter error. {:?}",e);
-                    },
-                };
+#[cfg(test)]
+mod tests {
+    use super::{create_scheme, parse_input, parse_salt};
+    use ethereum_types::{H160, U256};
+    use evm::backend::{MemoryBackend, MemoryVicinity};
+    use evm::executor::StackExecutor;
+    use evm::Config;
+    use std::collections::BTreeMap;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_create2_address() {
+        let vicinity = MemoryVicinity {
+            gas_price: U256::zero(),
+            origin: H160::zero(),
+            chain_id: U256::zero(),
+            block_hashes: Vec::new(),
+            block_number: U256::zero(),
+            block_coinbase: H160::zero(),
+            block_timestamp: U256::zero(),
+            block_difficulty: U256::zero(),
+            block_gas_limit: U256::zero(),
+        };
+        let backend = MemoryBackend::new(&vicinity, BTreeMap::new());
+        let config = Config::istanbul();
+        let executor = StackExecutor::new(&backend, 0, &config);
+        let address = |from: &str, salt: &str, code: &str| {
+            let from = H160::from_str(from).unwrap();
+            executor.create_address(create_scheme(from, Some(parse_salt(salt)), &hex::decode(code).unwrap()))
+        };
+
+        // Examples of EIP-1014.
+        assert_eq!(
+            address("0000000000000000000000000000000000000000", "0x00", "00"),
+            H160::from_str("4d1a2e2bb4f88f0250f26ffff098b0b30b26bf38").unwrap(),
+        );
+        assert_eq!(
+            address("00000000000000000000000000000000deadbeef", "0xcafebabe", "deadbeef"),
+            H160::from_str("60f3f640a8508fc6a86d45df051962668e1e8ac7").unwrap(),
+        );
+        assert_eq!(
+            address("00000000000000000000000000000000deadbeef", "0xcafebabe", "deadbeefdeadbeefdeadbeefdeadbeefdeadbeefdeadbeefdeadbeefdeadbeefdeadbeefdeadbeefdeadbeef"),
+            H160::from_str("1d8bfdc5d46dc4f61d6b6115972536ebe6a8854c").unwrap(),
+        );
+    }
 
-                return false;
-            }
-        }
+    #[test]
+    fn test_parse_input() {
+        assert_eq!(parse_input("6000"), vec![0x60, 0x00]);
+        assert_eq!(parse_input("0x6000\n"), vec![0x60, 0x00]);
+        assert_eq!(parse_input(""), Vec::<u8>::new());
     }
-}
\ No newline at end of file
+}
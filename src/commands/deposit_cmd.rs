@@ -3,33 +3,35 @@ use evm::backend::{ApplyBackend};
 use evm::executor::StackExecutor;
 use evm::Config;
 use bloom_state::State;
+use bloom_state::units::parse_amount;
+use ethereum_types::U256;
+use crate::executer::Error;
 
 
 // ./target/debug/evmbin deposit --from 0000000000000000000000000000000000000001 --value 1
+// ./target/debug/evmbin deposit --from 0000000000000000000000000000000000000001 --value 1.5ether
 #[derive(Debug, StructOpt, Clone)]
 pub struct DepositCmd {
 	#[structopt(long = "from")]
 	pub from: String,
-	#[structopt(long = "value")]
-	pub value: String,
+	#[structopt(long = "value", parse(try_from_str = parse_amount))]
+	pub value: U256,
 }
 
 
 impl DepositCmd {
-	pub fn run(&self, backend: &mut State) -> bool {
+	pub fn run(&self, backend: &mut State, config: &Config) -> Result<bool, Error> {
 		let from = self.from.parse().expect("From should be a valid address");
-		let value: u128 = self.value.parse().expect("Value is invalid");
 
-		let config = Config::istanbul();
 		let gas_limit = 100000;
 		let mut executor = StackExecutor::new(
 			backend,
 			gas_limit as usize,
-			&config,
+			config,
 		);
-		executor.deposit(from, value.into());
+		executor.deposit(from, self.value);
 		let (values, logs) = executor.deconstruct();
 		backend.apply(values, logs, true);
-		true
+		Ok(true)
 	}
 }
@@ -2,6 +2,10 @@ mod account_cmd;
 mod deposit_cmd;
 mod contract_cmd;
 mod state_cmd;
+mod receipt_cmd;
+mod tx_cmd;
+mod block_cmd;
+mod serve_cmd;
 
 use std::sync::Arc;
 
@@ -11,16 +15,33 @@ use account_cmd::AccountCmd;
 use deposit_cmd::DepositCmd;
 use contract_cmd::ContractCmd;
 use state_cmd::StateCmd;
+use receipt_cmd::ReceiptCmd;
+use tx_cmd::TxCmd;
+use block_cmd::BlockCmd;
+use serve_cmd::ServeCmd;
 
-use ethereum_types::{U256, H160, H256};
+use ethereum_types::{U256, H256};
+use evm::backend::Backend;
 use bloom_state as state;
 use ethtrie;
 use kvdb_rocksdb::{Database, DatabaseConfig};
 use trie_db::TrieSpec;
 use state::AccountFactory;
 use state::Factories;
+use state::{BackendVicinity, Block, Receipt, State};
 use std::str::FromStr; // !!! Necessary for H160::from_str(address).expect("...");
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
 use hex;
+use journaldb::Algorithm;
+
+use crate::Cli;
+use crate::fork::Fork;
+use crate::executer::{Error, Executed};
+use crate::transaction::MAX_CHAIN_ID;
+
+/// Number of commits kept in the journal before a pruning database canonicalizes them.
+const PRUNING_HISTORY: u64 = 64;
 
 
 
@@ -30,103 +51,101 @@ pub enum Subcommand {
 	Deposit(DepositCmd),
 	Contract(ContractCmd),
 	State(StateCmd),
+	Receipt(ReceiptCmd),
+	Tx(TxCmd),
+	Block(BlockCmd),
+	/// Serve the state over Ethereum JSON-RPC
+	Serve(ServeCmd),
 }
 
 impl Subcommand {
-	pub fn run(&self) {
-		let vicinity = state::BackendVicinity {
-			gas_price: U256::zero(),
-			origin: H160::zero(),
-			chain_id: U256::zero(),
-			block_hashes: Vec::new(),
-			block_number: U256::zero(),
-			block_coinbase: H160::zero(),
-			block_timestamp: U256::zero(),
-			block_difficulty: U256::zero(),
-			block_gas_limit: U256::zero(),
-		};
-		let data_path = "test-db";
+	/// Whether the command only reads the state, and so can inspect an earlier state.
+	fn is_read_only(&self) -> bool {
+		match self {
+			Subcommand::Account(cmd) => cmd.is_read_only(),
+			Subcommand::Contract(cmd) => cmd.is_read_only(),
+			_ => false,
+		}
+	}
+
+	pub fn run(&self, cli: &Cli) -> Result<(), Error> {
+		let mut vicinity = cli.vicinity.vicinity();
+		// Transactions are signed for the chain id as a u64.
+		if vicinity.chain_id > MAX_CHAIN_ID.into() {
+			return Err(Error::ChainIdTooHigh(vicinity.chain_id));
+		}
+		let data_path = cli.data_dir.as_str();
 		let config = DatabaseConfig::with_columns(state::COLUMN_COUNT);
 		let database = Arc::new(Database::open(&config, data_path).unwrap());
+		let algorithm = open_algorithm(&database, cli.pruning);
+		let config = open_fork(&database, cli.fork).config();
 
-		let count =
-		{
-			let default_ = [0u8;32].to_vec();
-			let v =  database.get(state::COL_BLOCK,b"root-count");
-
-			let count = v.unwrap_or(Some(default_.clone())).unwrap_or(default_.clone());
-			U256::from(count.as_slice())
-		};
-
-		let root =
-		{
-			let default_ = [0u8;32].to_vec();
-
-			let mut arr = [0u8;32];
-			count.to_big_endian(&mut arr);
-			let v =  database.get(state::COL_BLOCK,&arr[..]);
-
-			let root = v.unwrap_or(Some(default_.clone())).unwrap_or(default_.clone());
-			root.clone()
-		};
+		pending_block(cli, &database, &mut vicinity);
 
-
-		let root = H256::from_slice(root.as_slice());
-		//let root = H256::from_str("80df0689f530e11705a45c4f18a0da978902cc4b10b9728b244af8332b44ed2a").expect("");
-		//println!("get root={:?}",root.clone());
-
-
-		let db = journaldb::new(database.clone(),journaldb::Algorithm::Archive,state::COL_STATE);
-		let trie_layout = ethtrie::Layout::default();
-		let trie_spec = TrieSpec::Generic;
-
-		let gas_limit = 1000000u32;
-
-
-		let trie_factory =  ethtrie::TrieFactory::new(trie_spec,trie_layout);
-		let account_factory = AccountFactory::default();
-		let factories = Factories{
-			trie: trie_factory,
-			accountdb: account_factory,
-		};
-
-		let mut backend = match root == H256::zero() {
-			true => {
-				state::State::new(&vicinity,db,factories)
+		let (count, root) = head(&database);
+		let root = match &cli.at {
+			Some(at) if self.is_read_only() => at_block(cli, &database, count, at, &mut vicinity).unwrap_or_else(|e| {
+				eprintln!("{}", e);
+				process::exit(1);
+			}),
+			Some(_) => {
+				eprintln!("--at can only be used with account query, account list and contract call");
+				process::exit(1);
 			},
-			false => {
-				state::State::from_existing(root,&vicinity,db,factories).unwrap()
-			}
+			None => root,
 		};
+		let mut backend = open_state(&database, algorithm, root, &vicinity).unwrap_or_else(|e| {
+			eprintln!("{}", e);
+			process::exit(1);
+		});
 
-		let is_commit = match self {
+		let mut receipts = Vec::new();
+		let result = match self {
 			Subcommand::Account(cmd) => {
-				cmd.run(&mut backend)
+				cmd.run(&mut backend, &config, &cli.data_dir, &mut receipts)
 			}
 			Subcommand::Deposit(cmd) => {
-				cmd.run(&mut backend)
+				cmd.run(&mut backend, &config)
 			}
 			Subcommand::Contract(cmd) => {
-				cmd.run(&mut backend)
+				cmd.run(&mut backend, &config, &database, &mut receipts)
 			}
 			Subcommand::State(cmd) => {
-				cmd.run(database.clone(),count.clone())
+				Ok(cmd.run(database.clone(),count,algorithm,&mut backend))
+			}
+			Subcommand::Receipt(cmd) => {
+				Ok(cmd.run(database.clone(), count))
+			}
+			Subcommand::Tx(cmd) => {
+				cmd.run(&mut backend, &config, &mut receipts)
+			}
+			Subcommand::Block(cmd) => {
+				Ok(cmd.run(&database, count, &vicinity))
+			}
+			Subcommand::Serve(cmd) => {
+				// The server commits the transactions it is sent.
+				if let Some(fork) = cli.fork {
+					store_fork(&database, fork);
+				}
+				cmd.run(cli, &database, algorithm, &config)
 			}
 		};
 
-		if is_commit {
-			let root = backend.commit();
-			let v = count.as_u64();
-			let v = v + 1;
-			let new_count = U256::from(v);
-			let mut arr = [0u8;32];
-			new_count.to_big_endian(&mut arr);
+		// A transaction which failed during execution still pays its fee and has a receipt.
+		let is_commit = match result {
+			Ok(is_commit) => is_commit,
+			Err(_) => !receipts.is_empty(),
+		};
 
-			let mut transaction = database.transaction();
-			transaction.put(state::COL_BLOCK, b"root-count", &arr[..]);
-			transaction.put(state::COL_BLOCK, &arr[..],root.as_bytes());
-			database.write(transaction).unwrap();
+		if is_commit {
+			if let Some(fork) = cli.fork {
+				store_fork(&database, fork);
+			}
+			let root = commit(&database, algorithm, &mut backend, count, &receipts);
 			println!("set root={:?}",root.clone());
+			for receipt in &receipts {
+				println!("transaction hash={:?}", receipt.transaction_hash);
+			}
 		}
 
 		{
@@ -137,5 +156,359 @@ impl Subcommand {
 				//println!("val={:?}",v);
 			}
 		}
+
+		result.map(|_| ())
+	}
+}
+
+/// Print the gas used by an executed transaction, split into its intrinsic and execution gas.
+fn print_gas_used<R>(executed: &Executed<R>) {
+	println!("gas used={} (intrinsic: {}, execution: {})", executed.gas_used, executed.intrinsic_gas, executed.gas_used.saturating_sub(executed.intrinsic_gas));
+}
+
+/// Point the vicinity at the block pending after the latest mined one, unless the block number
+/// was given, and feed BLOCKHASH from the stored headers.
+///
+/// Unless given, the timestamp is the one of the transactions already pending, or the current
+/// time, so that the block is sealed with the timestamp its transactions saw.
+fn pending_block(cli: &Cli, database: &Database, vicinity: &mut BackendVicinity) {
+	if cli.vicinity.block_number.is_none() && cli.vicinity.env.is_none() {
+		vicinity.block_number = block_cmd::pending_number(database);
+	}
+	if cli.vicinity.timestamp.is_none() && cli.vicinity.env.is_none() {
+		vicinity.block_timestamp = block_cmd::pending_timestamp(database).unwrap_or_else(|| {
+			U256::from(SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs())
+		});
+	}
+	if vicinity.block_hashes.is_empty() {
+		vicinity.block_hashes = block_cmd::block_hashes(database, vicinity.block_number);
+	}
+}
+
+/// Get the number of the latest commit and its state root, zero for an empty database.
+fn head(database: &Database) -> (U256, H256) {
+	let count =
+	{
+		let default_ = [0u8;32].to_vec();
+		let v =  database.get(state::COL_BLOCK,b"root-count");
+
+		let count = v.unwrap_or(Some(default_.clone())).unwrap_or(default_.clone());
+		U256::from(count.as_slice())
+	};
+
+	let root =
+	{
+		let default_ = [0u8;32].to_vec();
+
+		let mut arr = [0u8;32];
+		count.to_big_endian(&mut arr);
+		let v =  database.get(state::COL_BLOCK,&arr[..]);
+
+		let root = v.unwrap_or(Some(default_.clone())).unwrap_or(default_.clone());
+		root.clone()
+	};
+
+	(count, H256::from_slice(root.as_slice()))
+}
+
+/// Point the vicinity at a mined block, unless the block number was given, as `pending_block`
+/// does for the pending block.
+fn sealed_block(cli: &Cli, database: &Database, block: &Block, vicinity: &mut BackendVicinity) {
+	if cli.vicinity.block_number.is_none() && cli.vicinity.env.is_none() {
+		vicinity.block_number = block.header.number;
+		vicinity.block_hashes = block_cmd::block_hashes(database, block.header.number);
+	}
+	if cli.vicinity.timestamp.is_none() && cli.vicinity.env.is_none() {
+		vicinity.block_timestamp = block.header.timestamp;
+	}
+}
+
+/// Resolve `--at` to the state root to open, pointing the vicinity at the block which sealed its
+/// commit. A commit not mined yet keeps the pending block.
+fn at_block(cli: &Cli, database: &Database, count: U256, at: &str, vicinity: &mut BackendVicinity) -> Result<H256, String> {
+	let (commit, root) = commit_at(database, count, at)?;
+	if let Some(block) = commit.and_then(|commit| block_cmd::block_of_commit(database, commit)) {
+		sealed_block(cli, database, &block, vicinity);
+	}
+	Ok(root)
+}
+
+/// Resolve the state root of a commit number up to the latest commit `count`, or of a state root.
+fn state_at(database: &Database, count: U256, at: &str) -> Result<H256, String> {
+	commit_at(database, count, at).map(|(_, root)| root)
+}
+
+/// Resolve a commit number up to the latest commit `count`, or a state root, to the commit and its
+/// state root. The commit of a state root is `None` when no commit recorded it.
+fn commit_at(database: &Database, count: U256, at: &str) -> Result<(Option<U256>, H256), String> {
+	let invalid = || format!("{} is not a valid commit number or state root", at);
+	if at.starts_with("0x") || at.len() == 64 {
+		let root = H256::from_str(at.trim_start_matches("0x")).map_err(|_| invalid())?;
+		let commit = (1..=count.as_u64()).rev()
+			.find(|number| root_at(database, *number) == root)
+			.map(U256::from)
+			.or_else(|| Some(U256::zero()).filter(|_| root.is_zero()));
+		return Ok((commit, root));
+	}
+
+	let number = U256::from_dec_str(at).map_err(|_| invalid())?;
+	if number > count {
+		return Err(format!("Commit {} is after the latest commit {}", number, count));
+	}
+	let root = match number.is_zero() {
+		true => H256::zero(),
+		false => root_at(database, number.as_u64()),
+	};
+	Ok((Some(number), root))
+}
+
+/// Open the state at the given root, a new state for the zero root.
+fn open_state<'a>(database: &Arc<Database>, algorithm: Algorithm, root: H256, vicinity: &'a BackendVicinity) -> Result<State<'a>, String> {
+	let db = journaldb::new(database.clone(),algorithm,state::COL_STATE);
+	let trie_layout = ethtrie::Layout::default();
+	let trie_spec = TrieSpec::Generic;
+
+	let trie_factory =  ethtrie::TrieFactory::new(trie_spec,trie_layout);
+	let account_factory = AccountFactory::default();
+	let factories = Factories{
+		trie: trie_factory,
+		accountdb: account_factory,
+	};
+
+	match root == H256::zero() {
+		true => {
+			Ok(state::State::new(vicinity,db,factories))
+		},
+		false => {
+			state::State::from_existing(root,vicinity,db,factories)
+				.map_err(|_| format!("State root {:?} is not available, it was pruned or never committed", root))
+		}
+	}
+}
+
+/// Commit the state after the commit `count` with the receipts of its transactions, returning the
+/// new state root.
+fn commit(database: &Database, algorithm: Algorithm, backend: &mut State, count: U256, receipts: &[Receipt]) -> H256 {
+	let v = count.as_u64();
+	let v = v + 1;
+	let canon = match algorithm {
+		Algorithm::Archive => None,
+		_ if v > PRUNING_HISTORY => {
+			let era = v - PRUNING_HISTORY;
+			Some((era, root_at(database, era)))
+		},
+		_ => None,
+	};
+	let root = backend.commit(v, canon);
+	let new_count = U256::from(v);
+	let mut arr = [0u8;32];
+	new_count.to_big_endian(&mut arr);
+
+	let mut transaction = database.transaction();
+	transaction.put(state::COL_BLOCK, b"root-count", &arr[..]);
+	transaction.put(state::COL_BLOCK, &arr[..],root.as_bytes());
+	if !receipts.is_empty() {
+		// The first transactions of the pending block set the timestamp it is sealed with.
+		if block_cmd::pending_timestamp(database).is_none() {
+			let mut timestamp = [0u8;32];
+			backend.block_timestamp().to_big_endian(&mut timestamp);
+			transaction.put(state::COL_HEADER, b"pending-timestamp", &timestamp[..]);
+		}
+		transaction.put(state::COL_RECEIPT, &arr[..], &rlp::encode_list(receipts));
+		for (index, receipt) in receipts.iter().enumerate() {
+			let mut stream = rlp::RlpStream::new_list(2);
+			stream.append(&new_count);
+			stream.append(&index);
+			transaction.put(state::COL_RECEIPT, receipt.transaction_hash.as_bytes(), &stream.out());
+		}
+	}
+	database.write(transaction).unwrap();
+	root
+}
+
+/// Resolve the pruning algorithm of the database, recording it on first open.
+///
+/// A database which already holds state without a recorded algorithm was written by the archive
+/// algorithm. Reopening a database with a different algorithm than the recorded one is an error.
+fn open_algorithm(database: &Database, requested: Option<Algorithm>) -> Algorithm {
+	let stored = match database.get(state::COL_BLOCK, b"pruning").unwrap() {
+		Some(v) => {
+			let name = String::from_utf8(v).expect("Stored pruning algorithm is invalid");
+			Some(Algorithm::from_str(&name).expect("Stored pruning algorithm is invalid"))
+		},
+		None => database.get(state::COL_BLOCK, b"root-count").unwrap().map(|_| Algorithm::Archive),
+	};
+
+	match (stored, requested) {
+		(Some(stored), Some(requested)) if stored != requested => {
+			eprintln!("Database was created with `{}` pruning and cannot be reopened with `{}`", stored, requested);
+			process::exit(1);
+		},
+		(Some(stored), _) => stored,
+		(None, requested) => {
+			let algorithm = requested.unwrap_or(Algorithm::Archive);
+			let mut transaction = database.transaction();
+			transaction.put(state::COL_BLOCK, b"pruning", algorithm.as_str().as_bytes());
+			database.write(transaction).unwrap();
+			algorithm
+		},
+	}
+}
+
+/// Resolve the hard fork used for execution, the requested one or else the recorded default.
+fn open_fork(database: &Database, requested: Option<Fork>) -> Fork {
+	match requested {
+		Some(fork) => fork,
+		None => match database.get(state::COL_BLOCK, b"fork").unwrap() {
+			Some(v) => {
+				let name = String::from_utf8(v).expect("Stored fork is invalid");
+				Fork::from_str(&name).expect("Stored fork is invalid")
+			},
+			None => Fork::Istanbul,
+		},
+	}
+}
+
+/// Record the fork as the default of the database, once a command changed the state with it.
+fn store_fork(database: &Database, fork: Fork) {
+	let mut transaction = database.transaction();
+	transaction.put(state::COL_BLOCK, b"fork", fork.as_str().as_bytes());
+	database.write(transaction).unwrap();
+}
+
+/// Get the state root recorded for the given commit number.
+fn root_at(database: &Database, number: u64) -> H256 {
+	let mut arr = [0u8;32];
+	U256::from(number).to_big_endian(&mut arr);
+	let root = database.get(state::COL_BLOCK, &arr[..]).unwrap().expect("Commit root not found");
+	H256::from_slice(root.as_slice())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{at_block, block_cmd, commit, head, open_algorithm, open_state, pending_block, root_at, state_at};
+	use std::{env, fs, process};
+	use bloom_state as state;
+	use kvdb_rocksdb::{Database, DatabaseConfig};
+	use ethereum_types::{H160, H256, U256};
+	use evm::backend::ApplyBackend;
+	use journaldb::Algorithm;
+	use bloom_state::BackendVicinity;
+	use structopt::StructOpt;
+
+	use crate::Cli;
+	use crate::executer::Error;
+	use crate::test_helpers::{account, TempDatabase};
+
+	#[test]
+	fn test_at_block() {
+		let database = TempDatabase::new("at-block");
+		let vicinity = BackendVicinity::default();
+		let deposit = |balance: u64| {
+			let (count, root) = head(&database);
+			let mut backend = open_state(&database, Algorithm::Archive, root, &vicinity).unwrap();
+			backend.apply(vec![account(H160::from_low_u64_be(1), balance, None, &[])], Vec::new(), false);
+			commit(&database, Algorithm::Archive, &mut backend, count, &[])
+		};
+		let mine = |count: u64, timestamp: u64| {
+			block_cmd::mine(&database, U256::from(count), &BackendVicinity { block_timestamp: U256::from(timestamp), ..Default::default() })
+		};
+
+		// Block 0 seals commit 0, block 1 the commits 1 and 2, and commit 3 is pending.
+		let block0 = mine(0, 50);
+		deposit(1);
+		deposit(2);
+		let block1 = mine(2, 100);
+		let root3 = deposit(3);
+
+		let cli = Cli::from_iter(&["bloom-evm"]);
+		let at = |cli: &Cli, at: &str| {
+			let mut vicinity = cli.vicinity.vicinity();
+			pending_block(cli, &database, &mut vicinity);
+			at_block(cli, &database, U256::from(3), at, &mut vicinity).map(|root| (root, vicinity))
+		};
+
+		let (root, vicinity) = at(&cli, "2").unwrap();
+		assert_eq!(root, root_at(&database, 2));
+		assert_eq!(vicinity.block_number, U256::one());
+		assert_eq!(vicinity.block_hashes, vec![block0.header.hash()]);
+		assert_eq!(vicinity.block_timestamp, U256::from(100));
+		assert_eq!(at(&cli, "1").unwrap().1.block_number, U256::one());
+
+		let (root, vicinity) = at(&cli, "0").unwrap();
+		assert_eq!(root, H256::zero());
+		assert_eq!(vicinity.block_number, U256::zero());
+		assert!(vicinity.block_hashes.is_empty());
+
+		// A state root resolves to its commit, here still pending.
+		let (root, vicinity) = at(&cli, &format!("{:?}", root3)).unwrap();
+		assert_eq!(root, root3);
+		assert_eq!(vicinity.block_number, U256::from(2));
+		assert_eq!(vicinity.block_hashes, vec![block1.header.hash(), block0.header.hash()]);
+		let root2 = format!("{:x}", root_at(&database, 2));
+		assert_eq!(at(&cli, &root2).unwrap().1.block_number, U256::one());
+		let unknown = H256::repeat_byte(1);
+		assert_eq!(at(&cli, &format!("{:?}", unknown)).unwrap().0, unknown);
+
+		// The block number given overrides the one of the commit.
+		let cli_number = Cli::from_iter(&["bloom-evm", "--block-number", "7"]);
+		assert_eq!(at(&cli_number, "2").unwrap().1.block_number, U256::from(7));
+
+		assert!(at(&cli, "4").is_err());
+		assert!(at(&cli, "latest").is_err());
+		assert_eq!(state_at(&database, U256::from(3), "2"), Ok(root_at(&database, 2)));
+		assert!(state_at(&database, U256::from(3), "4").is_err());
+	}
+
+	#[test]
+	fn test_pruning_option() {
+		let pruning = |name: &str| Cli::from_iter(&["bloom-evm", "--pruning", name]).pruning;
+		assert_eq!(pruning("archive"), Some(Algorithm::Archive));
+		assert_eq!(pruning("fast"), Some(Algorithm::OverlayRecent));
+		assert_eq!(pruning("overlayrecent"), Some(Algorithm::OverlayRecent));
+		assert_eq!(pruning("light"), Some(Algorithm::EarlyMerge));
+		assert_eq!(pruning("earlymerge"), Some(Algorithm::EarlyMerge));
+		assert_eq!(pruning("basic"), Some(Algorithm::RefCounted));
+		assert_eq!(pruning("refcounted"), Some(Algorithm::RefCounted));
+		assert!(Cli::from_iter_safe(&["bloom-evm", "--pruning", "full"]).is_err());
+
+		// The algorithm of the first open is recorded and used by the later ones.
+		let database = TempDatabase::new("pruning");
+		assert_eq!(open_algorithm(&database, pruning("overlayrecent")), Algorithm::OverlayRecent);
+		assert_eq!(open_algorithm(&database, None), Algorithm::OverlayRecent);
+		assert_eq!(open_algorithm(&database, pruning("fast")), Algorithm::OverlayRecent);
+	}
+
+	#[test]
+	fn test_fork_default() {
+		let path = env::temp_dir().join(format!("bloom-evm-fork-{}", process::id()));
+		let data_dir = path.to_str().unwrap();
+		let run = |args: &[&str]| {
+			let cli = Cli::from_iter(["bloom-evm", "--data-dir", data_dir].iter().chain(args));
+			cli.subcmd.as_ref().unwrap().run(&cli).unwrap();
+		};
+		let stored_fork = || {
+			let database = Database::open(&DatabaseConfig::with_columns(state::COLUMN_COUNT), data_dir).unwrap();
+			database.get(state::COL_BLOCK, b"fork").unwrap().map(|v| String::from_utf8(v).unwrap())
+		};
+
+		// A read-only run executes with the fork without recording it.
+		run(&["--fork", "byzantium", "account", "list"]);
+		assert_eq!(stored_fork(), None);
+		run(&["--fork", "byzantium", "deposit", "--from", "0000000000000000000000000000000000000001", "--value", "1"]);
+		assert_eq!(stored_fork(), Some("byzantium".to_string()));
+		run(&["--fork", "istanbul", "account", "list"]);
+		assert_eq!(stored_fork(), Some("byzantium".to_string()));
+		fs::remove_dir_all(&path).unwrap();
+	}
+
+	#[test]
+	fn test_chain_id_too_high() {
+		// Rejected before the data directory is opened.
+		let cli = Cli::from_iter(&["bloom-evm", "--chain-id", "9223372036854775790", "account", "list"]);
+		match cli.subcmd.as_ref().unwrap().run(&cli) {
+			Err(Error::ChainIdTooHigh(chain_id)) => assert_eq!(chain_id, U256::from(9223372036854775790u64)),
+			other => panic!("unexpected result {:?}", other.err()),
+		}
 	}
 }
@@ -0,0 +1,79 @@
+use structopt::StructOpt;
+use kvdb_rocksdb::{Database};
+use bloom_state as state;
+use state::Receipt;
+use ethereum_types::{U256,H256};
+
+use std::process;
+use std::str::FromStr;
+use std::sync::Arc;
+
+// ./target/debug/bloom-evm receipt --hash 4f5e...
+// ./target/debug/bloom-evm receipt --commit 3 --index 0
+
+#[derive(Debug, StructOpt, Clone)]
+pub struct ReceiptCmd {
+    /// The transaction hash
+    #[structopt(long = "hash")]
+    hash: Option<String>,
+
+    /// The commit number holding the transaction, default the latest commit
+    #[structopt(long = "commit")]
+    commit: Option<String>,
+
+    /// The index of the transaction in the commit, default 0
+    #[structopt(long = "index", default_value = "0")]
+    index: usize,
+}
+
+impl ReceiptCmd {
+    pub fn run(&self, db: Arc<Database>, count: U256) -> bool {
+        let (commit, index) = match &self.hash {
+            Some(hash) => {
+                let hash = H256::from_str(hash.trim_start_matches("0x")).expect("--hash argument must be a valid hash");
+                match db.get(state::COL_RECEIPT, hash.as_bytes()).unwrap() {
+                    Some(v) => {
+                        let location = rlp::Rlp::new(&v);
+                        let commit: U256 = location.val_at(0).expect("Stored receipt location is invalid");
+                        let index: usize = location.val_at(1).expect("Stored receipt location is invalid");
+                        (commit, index)
+                    },
+                    None => {
+                        eprintln!("Transaction {:?} not found", hash);
+                        process::exit(1);
+                    },
+                }
+            },
+            None => {
+                let commit = match &self.commit {
+                    Some(commit) => U256::from_dec_str(commit).expect("--commit argument must be a valid number"),
+                    None => count,
+                };
+                (commit, self.index)
+            },
+        };
+
+        let receipts = receipts_at(&db, commit);
+        match receipts.get(index) {
+            Some(receipt) => {
+                println!("commit: {}, index: {}", commit, index);
+                println!("{}", receipt);
+            },
+            None => {
+                eprintln!("No transaction {} in commit {}", index, commit);
+                process::exit(1);
+            },
+        }
+        false
+    }
+}
+
+/// Get the receipts of the transactions of the given commit.
+pub(super) fn receipts_at(db: &Database, commit: U256) -> Vec<Receipt> {
+    let mut arr = [0u8;32];
+    commit.to_big_endian(&mut arr);
+    match db.get(state::COL_RECEIPT, &arr[..]).unwrap() {
+        Some(v) => rlp::decode_list(&v),
+        None => Vec::new(),
+    }
+}
@@ -0,0 +1,645 @@
+use structopt::StructOpt;
+use kvdb_rocksdb::Database;
+use bloom_state as state;
+use state::{BackendVicinity, Block, Receipt};
+use evm::backend::Backend;
+use evm::executor::StackExecutor;
+use evm::Config;
+use ethereum_types::{H160, H256, U256};
+use journaldb::Algorithm;
+use serde_json::{json, Value};
+
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::panic::{self, AssertUnwindSafe};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::Cli;
+use crate::executer::{self, Error};
+use crate::transaction::SignedTransaction;
+use super::block_cmd;
+use super::tx_cmd;
+
+// ./target/debug/bloom-evm serve --http 127.0.0.1:8545
+// curl -X POST -H 'Content-Type: application/json' --data '{"jsonrpc":"2.0","id":1,"method":"eth_blockNumber","params":[]}' http://127.0.0.1:8545
+
+/// Gas limit of `eth_call` and cap of `eth_estimateGas` when the request gives none.
+const DEFAULT_GAS: u64 = 10_000_000;
+
+/// Largest request body accepted, as geth does.
+const MAX_BODY_SIZE: usize = 5 * 1024 * 1024;
+
+/// Time given to a client to send its request and to read the response, as the server answers one
+/// connection at a time.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Serve the state over Ethereum JSON-RPC. Every transaction sent is committed and sealed into its
+/// own block.
+#[derive(Debug, StructOpt, Clone)]
+pub struct ServeCmd {
+    /// The address the HTTP server listens on
+    #[structopt(long = "http", default_value = "127.0.0.1:8545")]
+    http: String,
+}
+
+impl ServeCmd {
+    pub fn run(&self, cli: &Cli, database: &Arc<Database>, algorithm: Algorithm, config: &Config) -> Result<bool, Error> {
+        let listener = TcpListener::bind(&self.http)
+            .unwrap_or_else(|e| panic!("--http address {} can't be listened on: {}", self.http, e));
+        println!("JSON-RPC server listening on http://{}", self.http);
+
+        let server = Server { cli, database, algorithm, config };
+        for stream in listener.incoming() {
+            let served = stream.and_then(|stream| server.serve(stream));
+            if let Err(e) = served {
+                eprintln!("Request failed: {}", e);
+            }
+        }
+        Ok(false)
+    }
+}
+
+/// Error of a JSON-RPC request.
+struct RpcError {
+    code: i64,
+    message: String,
+    data: Option<Value>,
+}
+
+impl RpcError {
+    fn new<T: fmt::Display>(code: i64, message: T) -> Self {
+        RpcError { code, message: message.to_string(), data: None }
+    }
+
+    fn invalid_params<T: fmt::Display>(message: T) -> Self {
+        RpcError::new(-32602, message)
+    }
+}
+
+impl From<Error> for RpcError {
+    fn from(e: Error) -> Self {
+        match &e {
+            // Reverts carry their data for the client to decode, as geth does.
+            Error::ExitReasonRevert(_, data) => RpcError {
+                code: 3,
+                message: e.to_string(),
+                data: Some(bytes(data)),
+            },
+            Error::GasLimitOverflow(_) => RpcError::invalid_params(e),
+            _ => RpcError::new(-32000, e),
+        }
+    }
+}
+
+struct Server<'a> {
+    cli: &'a Cli,
+    database: &'a Arc<Database>,
+    algorithm: Algorithm,
+    config: &'a Config,
+}
+
+impl<'a> Server<'a> {
+    /// Answer one HTTP request of the connection.
+    fn serve(&self, mut stream: TcpStream) -> io::Result<()> {
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line)?;
+
+        let mut content_length = 0;
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+                break;
+            }
+            if let Some((name, value)) = line.split_once(':') {
+                if name.trim().eq_ignore_ascii_case("content-length") {
+                    content_length = value.trim().parse().map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "invalid Content-Length"))?;
+                }
+            }
+        }
+        let mut body = Vec::new();
+        if content_length <= MAX_BODY_SIZE {
+            body.resize(content_length, 0);
+            reader.read_exact(&mut body)?;
+        }
+
+        let (status, body) = match request_line.split_whitespace().next() {
+            Some("POST") if content_length > MAX_BODY_SIZE => ("413 Payload Too Large", String::new()),
+            Some("POST") => ("200 OK", self.respond(&body)),
+            // Preflight of the requests of a browser frontend.
+            Some("OPTIONS") => ("204 No Content", String::new()),
+            _ => ("405 Method Not Allowed", String::new()),
+        };
+        write!(
+            stream,
+            "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nAccess-Control-Allow-Origin: *\r\nAccess-Control-Allow-Methods: POST, OPTIONS\r\nAccess-Control-Allow-Headers: Content-Type\r\nConnection: close\r\n\r\n{}",
+            status,
+            body.len(),
+            body,
+        )?;
+        stream.flush()
+    }
+
+    /// Answer a JSON-RPC request or a batch of requests.
+    fn respond(&self, body: &[u8]) -> String {
+        let response = match serde_json::from_slice::<Value>(body) {
+            Ok(Value::Array(requests)) if !requests.is_empty() => {
+                Value::Array(requests.iter().map(|request| self.call(request)).collect())
+            },
+            Ok(request) => self.call(&request),
+            Err(e) => response(Value::Null, Err(RpcError::new(-32700, format!("Parse error: {}", e)))),
+        };
+        response.to_string()
+    }
+
+    fn call(&self, request: &Value) -> Value {
+        let id = request.get("id").cloned().unwrap_or(Value::Null);
+        let method = request.get("method").and_then(Value::as_str);
+        let params = request.get("params").cloned().unwrap_or_else(|| json!([]));
+        let result = match (method, params) {
+            // A request hitting a bug or a corrupted database fails alone, the server goes on.
+            (Some(method), Value::Array(params)) => panic::catch_unwind(AssertUnwindSafe(|| self.method(method, &params)))
+                .unwrap_or_else(|e| {
+                    let message = e.downcast_ref::<&str>().copied()
+                        .or_else(|| e.downcast_ref::<String>().map(String::as_str))
+                        .unwrap_or("unknown error");
+                    Err(RpcError::new(-32603, format!("Internal error: {}", message)))
+                }),
+            _ => Err(RpcError::new(-32600, "Invalid request")),
+        };
+        response(id, result)
+    }
+
+    /// Resolve a block parameter to the state root to read, pointing the vicinity at the block.
+    ///
+    /// `latest` and `pending` read the state with the pending transactions, `head`, as the
+    /// methods without a block parameter do. A block number or `earliest` reads the state sealed by
+    /// that block, which a pruning database may no longer have.
+    fn block_root(&self, block: &Value, head: H256, vicinity: &mut BackendVicinity) -> Result<H256, RpcError> {
+        let db = self.database.as_ref();
+        let number = match block.as_str() {
+            Some("latest") | Some("pending") => return Ok(head),
+            Some("earliest") => U256::zero(),
+            _ => number(block)?,
+        };
+        let block = block_cmd::block_at(db, number)
+            .ok_or_else(|| RpcError::invalid_params(format!("Block {} not found", number)))?;
+        super::sealed_block(self.cli, db, &block, vicinity);
+        Ok(match block.commit.is_zero() {
+            true => H256::zero(),
+            false => block.header.state_root,
+        })
+    }
+
+    fn method(&self, method: &str, params: &[Value]) -> Result<Value, RpcError> {
+        let db = self.database.as_ref();
+        let mut vicinity = self.cli.vicinity.vicinity();
+        super::pending_block(self.cli, db, &mut vicinity);
+        let (count, root) = super::head(db);
+        // The methods reading the state take the block to read it at after their parameters.
+        let block = match method {
+            "eth_getBalance" | "eth_getTransactionCount" | "eth_getCode" | "eth_call" => params.get(1),
+            "eth_getStorageAt" => params.get(2),
+            _ => None,
+        };
+        let root = match block {
+            Some(block) => self.block_root(block, root, &mut vicinity)?,
+            None => root,
+        };
+        let mut backend = super::open_state(self.database, self.algorithm, root, &vicinity)
+            .map_err(|e| RpcError::new(-32000, e))?;
+
+        match method {
+            "eth_chainId" => Ok(quantity(vicinity.chain_id)),
+
+            "eth_blockNumber" => {
+                Ok(quantity(block_cmd::latest_block(db).map_or(U256::zero(), |block| block.header.number)))
+            },
+
+            "eth_getBalance" => {
+                Ok(quantity(backend.basic(address(param(params, 0)?)?).balance))
+            },
+
+            "eth_getTransactionCount" => {
+                Ok(quantity(backend.basic(address(param(params, 0)?)?).nonce))
+            },
+
+            "eth_getCode" => {
+                Ok(bytes(&backend.code(address(param(params, 0)?)?)))
+            },
+
+            "eth_getStorageAt" => {
+                let mut key = H256::zero();
+                number(param(params, 1)?)?.to_big_endian(key.as_bytes_mut());
+                Ok(json!(format!("{:?}", backend.storage(address(param(params, 0)?)?, key))))
+            },
+
+            "eth_call" => {
+                let call = CallRequest::parse(param(params, 0)?)?;
+                let gas = executer::gas_usize(call.gas.unwrap_or(DEFAULT_GAS))?;
+                let mut executor = StackExecutor::new(&backend, gas, self.config);
+                let (reason, output) = match call.to {
+                    Some(to) => executor.transact_call(call.from, to, call.value, call.data, gas),
+                    None => executor.transact_create(call.from, call.value, call.data, gas),
+                };
+                Ok(bytes(&executer::exit_result(reason, output)?))
+            },
+
+            "eth_estimateGas" => {
+                let call = CallRequest::parse(param(params, 0)?)?;
+                let gas_price = call.gas_price.unwrap_or(vicinity.block_base_fee);
+                let intrinsic_gas = executer::intrinsic_gas(self.config, call.to.is_none(), &call.data);
+                let (gas_limit, _) = executer::estimate_gas(call.gas.unwrap_or(DEFAULT_GAS), |gas_limit| {
+                    let gas = executer::gas_usize(gas_limit)?;
+                    let mut state = backend.clone();
+                    let data = call.data.clone();
+                    executer::execute_evm(
+                        call.from,
+                        call.value,
+                        gas_limit,
+                        gas_price,
+                        None,
+                        intrinsic_gas,
+                        |executor| ((), match call.to {
+                            Some(to) => executor.transact_call(call.from, to, call.value, data, gas),
+                            None => executor.transact_create(call.from, call.value, data, gas),
+                        }),
+                        self.config,
+                        &mut state
+                    )
+                })?;
+                Ok(quantity(gas_limit))
+            },
+
+            "eth_sendRawTransaction" => {
+                let raw = param(params, 0)?.as_str().ok_or_else(|| RpcError::invalid_params("Raw transaction must be a hex string"))?;
+                let tx = SignedTransaction::from_hex(raw).map_err(RpcError::invalid_params)?;
+
+                let (contract_address, executed) = tx_cmd::execute(&tx, None, &mut backend, self.config)?;
+                let mut receipts = Vec::new();
+                // A transaction which failed during execution is still committed, as its fee is paid.
+                let _ = executed.record(tx.hash, contract_address, &mut receipts);
+                let root = super::commit(db, self.algorithm, &mut backend, count, &receipts);
+                let block = block_cmd::mine(db, count + 1, &vicinity);
+                println!("set root={:?}", root);
+                println!("Mined block {} {:?} with transaction {:?}", block.header.number, block.header.hash(), tx.hash);
+                Ok(json!(format!("{:?}", tx.hash)))
+            },
+
+            "eth_getTransactionReceipt" => {
+                let hash = hash(param(params, 0)?)?;
+                let location = match db.get(state::COL_RECEIPT, hash.as_bytes()).unwrap() {
+                    Some(v) => v,
+                    None => return Ok(Value::Null),
+                };
+                let commit: U256 = rlp::Rlp::new(&location).val_at(0).expect("Stored receipt location is invalid");
+
+                // The receipts of the block count the gas and the logs of the transactions before.
+                let block = block_cmd::block_of_commit(db, commit);
+                let receipts = match &block {
+                    Some(block) => block_cmd::block_receipts(db, block),
+                    None => {
+                        let after = block_cmd::latest_block(db).map_or(U256::zero(), |block| block.commit);
+                        block_cmd::receipts_between(db, after, commit)
+                    },
+                };
+                let index = receipts.iter().position(|r| r.transaction_hash == hash).expect("Stored receipt location is invalid");
+                let mut log_index = receipts[..index].iter().map(|r| r.logs.len()).sum();
+                Ok(receipt_json(&receipts[index], index, block.as_ref(), &mut log_index))
+            },
+
+            "eth_getLogs" => {
+                let filter = LogFilter::parse(param(params, 0)?, db)?;
+                let mut logs = Vec::new();
+                let mut number = filter.from_block;
+                while number <= filter.to_block {
+                    let block = match block_cmd::block_at(db, number) {
+                        Some(block) => block,
+                        None => break,
+                    };
+                    let mut log_index = 0;
+                    for (index, receipt) in block_cmd::block_receipts(db, &block).iter().enumerate() {
+                        for log in receipt_json(receipt, index, Some(&block), &mut log_index)["logs"].as_array().unwrap() {
+                            if filter.matches(log) {
+                                logs.push(log.clone());
+                            }
+                        }
+                    }
+                    number = number + 1;
+                }
+                Ok(Value::Array(logs))
+            },
+
+            _ => Err(RpcError::new(-32601, format!("Method {} not found", method))),
+        }
+    }
+}
+
+/// Transaction fields of `eth_call` and `eth_estimateGas`.
+struct CallRequest {
+    from: H160,
+    to: Option<H160>,
+    gas: Option<u64>,
+    gas_price: Option<U256>,
+    value: U256,
+    data: Vec<u8>,
+}
+
+impl CallRequest {
+    fn parse(request: &Value) -> Result<Self, RpcError> {
+        let field = |name: &str| request.get(name).filter(|v| !v.is_null());
+        let gas = match field("gas") {
+            Some(gas) => {
+                let gas = number(gas)?;
+                if gas > u64::MAX.into() {
+                    return Err(RpcError::invalid_params("Gas limit is too high"));
+                }
+                Some(gas.as_u64())
+            },
+            None => None,
+        };
+
+        Ok(CallRequest {
+            from: field("from").map(address).transpose()?.unwrap_or_default(),
+            to: field("to").map(address).transpose()?,
+            gas,
+            gas_price: field("gasPrice").map(number).transpose()?,
+            value: field("value").map(number).transpose()?.unwrap_or_default(),
+            data: field("data").or_else(|| field("input")).map(data).transpose()?.unwrap_or_default(),
+        })
+    }
+}
+
+/// Filter of `eth_getLogs`.
+struct LogFilter {
+    from_block: U256,
+    to_block: U256,
+    addresses: Vec<Value>,
+    /// Accepted values of each topic, any value for an empty list.
+    topics: Vec<Vec<Value>>,
+}
+
+impl LogFilter {
+    fn parse(filter: &Value, db: &Database) -> Result<Self, RpcError> {
+        let latest = block_cmd::latest_block(db).map_or(U256::zero(), |block| block.header.number);
+        let block = |name: &str| match filter.get(name).and_then(Value::as_str) {
+            None | Some("latest") | Some("pending") => Ok(latest),
+            Some("earliest") => Ok(U256::zero()),
+            Some(_) => number(&filter[name]),
+        };
+        // Either a value or a list of accepted values.
+        let one_of = |v: &Value| match v {
+            Value::Null => Vec::new(),
+            Value::Array(values) => values.iter().map(lowercase).collect(),
+            v => vec![lowercase(v)],
+        };
+
+        Ok(LogFilter {
+            from_block: block("fromBlock")?,
+            to_block: block("toBlock")?,
+            addresses: filter.get("address").map_or_else(Vec::new, one_of),
+            topics: match filter.get("topics") {
+                Some(Value::Array(topics)) => topics.iter().map(one_of).collect(),
+                _ => Vec::new(),
+            },
+        })
+    }
+
+    fn matches(&self, log: &Value) -> bool {
+        let accepts = |accepted: &Vec<Value>, v: &Value| accepted.is_empty() || accepted.contains(v);
+        let topics = log["topics"].as_array().unwrap();
+        accepts(&self.addresses, &log["address"]) &&
+            self.topics.iter().enumerate().all(|(i, accepted)| {
+                accepted.is_empty() || topics.get(i).map(|topic| accepted.contains(topic)) == Some(true)
+            })
+    }
+}
+
+/// Receipt of the transaction at `index` in the block, numbering its logs from `log_index`.
+fn receipt_json(receipt: &Receipt, index: usize, block: Option<&Block>, log_index: &mut usize) -> Value {
+    let block_number = block.map(|block| quantity(block.header.number)).unwrap_or(Value::Null);
+    let block_hash = block.map(|block| json!(format!("{:?}", block.header.hash()))).unwrap_or(Value::Null);
+    let transaction_hash = format!("{:?}", receipt.transaction_hash);
+
+    let logs: Vec<Value> = receipt.logs.iter().map(|log| {
+        let log = json!({
+            "address": format!("{:?}", log.address),
+            "topics": log.topics.iter().map(|topic| format!("{:?}", topic)).collect::<Vec<_>>(),
+            "data": bytes(&log.data),
+            "blockNumber": block_number,
+            "blockHash": block_hash,
+            "transactionHash": transaction_hash,
+            "transactionIndex": quantity(index),
+            "logIndex": quantity(*log_index),
+            "removed": false,
+        });
+        *log_index += 1;
+        log
+    }).collect();
+
+    json!({
+        "transactionHash": transaction_hash,
+        "transactionIndex": quantity(index),
+        "blockNumber": block_number,
+        "blockHash": block_hash,
+        "cumulativeGasUsed": quantity(receipt.cumulative_gas_used),
+        "gasUsed": quantity(receipt.gas_used),
+        "contractAddress": receipt.contract_address.map(|address| format!("{:?}", address)),
+        "logs": logs,
+        "logsBloom": format!("{:?}", receipt.logs_bloom),
+        "status": quantity(receipt.status as u8),
+    })
+}
+
+fn response(id: Value, result: Result<Value, RpcError>) -> Value {
+    match result {
+        Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+        Err(e) => {
+            let mut error = json!({"code": e.code, "message": e.message});
+            if let Some(data) = e.data {
+                error["data"] = data;
+            }
+            json!({"jsonrpc": "2.0", "id": id, "error": error})
+        },
+    }
+}
+
+fn param(params: &[Value], index: usize) -> Result<&Value, RpcError> {
+    params.get(index).ok_or_else(|| RpcError::invalid_params(format!("Missing parameter {}", index)))
+}
+
+/// A quantity, as hex without leading zeros.
+fn quantity<T: Into<U256>>(value: T) -> Value {
+    json!(format!("{:#x}", value.into()))
+}
+
+/// Bytes, as 0x prefixed hex.
+fn bytes(data: &[u8]) -> Value {
+    json!(format!("0x{}", hex::encode(data)))
+}
+
+fn lowercase(v: &Value) -> Value {
+    v.as_str().map_or(Value::Null, |s| json!(s.to_lowercase()))
+}
+
+fn hex_param(v: &Value) -> Result<&str, RpcError> {
+    v.as_str()
+        .and_then(|s| s.strip_prefix("0x"))
+        .ok_or_else(|| RpcError::invalid_params(format!("Expected a 0x prefixed hex string, got {}", v)))
+}
+
+fn number(v: &Value) -> Result<U256, RpcError> {
+    let hex = hex_param(v)?;
+    if hex.is_empty() || hex.len() > 64 {
+        return Err(RpcError::invalid_params(format!("Invalid quantity {}", v)));
+    }
+    U256::from_str(hex).map_err(|_| RpcError::invalid_params(format!("Invalid quantity {}", v)))
+}
+
+fn data(v: &Value) -> Result<Vec<u8>, RpcError> {
+    hex::decode(hex_param(v)?).map_err(|_| RpcError::invalid_params(format!("Invalid data {}", v)))
+}
+
+fn address(v: &Value) -> Result<H160, RpcError> {
+    H160::from_str(hex_param(v)?).map_err(|_| RpcError::invalid_params(format!("Invalid address {}", v)))
+}
+
+fn hash(v: &Value) -> Result<H256, RpcError> {
+    H256::from_str(hex_param(v)?).map_err(|_| RpcError::invalid_params(format!("Invalid hash {}", v)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{block_cmd, number, quantity, LogFilter, Server};
+    use bloom_state as state;
+    use ethereum_types::{H160, H256, U256};
+    use evm::backend::ApplyBackend;
+    use evm::Config;
+    use journaldb::Algorithm;
+    use serde_json::{json, Value};
+    use structopt::StructOpt;
+
+    use crate::Cli;
+    use crate::test_helpers::{account, TempDatabase};
+    use crate::transaction::SignedTransaction;
+
+    #[test]
+    fn test_send_raw_transaction() {
+        let database = TempDatabase::new("serve");
+        let cli = Cli::from_iter(&["bloom-evm", "--chain-id", "1"]);
+        let config = Config::istanbul();
+        let secret = secp256k1::SecretKey::parse(&[0x46; 32]).unwrap();
+        let sender = H160::from_slice(&hex::decode("9d8a62f656a8d1615c1294fd71e9cfb3e4855a4f").unwrap());
+        let receiver = H160::from_low_u64_be(0xa);
+
+        let vicinity = cli.vicinity.vicinity();
+        let mut backend = super::super::open_state(&database, Algorithm::Archive, H256::zero(), &vicinity).unwrap();
+        backend.apply(vec![account(sender, 1000000, None, &[])], Vec::new(), false);
+        super::super::commit(&database, Algorithm::Archive, &mut backend, U256::zero(), &[]);
+
+        let server = Server { cli: &cli, database: &database, algorithm: Algorithm::Archive, config: &config };
+        let request = |method: &str, params: Value| -> Value {
+            let body = json!({"jsonrpc": "2.0", "id": 1, "method": method, "params": params});
+            serde_json::from_str(&server.respond(body.to_string().as_bytes())).unwrap()
+        };
+
+        let tx = SignedTransaction::sign(U256::zero(), U256::one(), U256::from(21000), Some(receiver), U256::from(5), Vec::new(), 1, &secret);
+        let raw = format!("0x{}", hex::encode(rlp::encode(&tx)));
+        let hash = json!(format!("{:?}", tx.hash));
+        assert_eq!(request("eth_sendRawTransaction", json!([raw]))["result"], hash);
+
+        let receipt = &request("eth_getTransactionReceipt", json!([hash]))["result"];
+        assert_eq!(receipt["transactionHash"], hash);
+        assert_eq!(receipt["status"], json!("0x1"));
+        assert_eq!(receipt["gasUsed"], json!("0x5208"));
+        assert_eq!(receipt["blockNumber"], json!("0x0"));
+        assert_eq!(request("eth_blockNumber", json!([]))["result"], json!("0x0"));
+        assert_eq!(request("eth_getBalance", json!([format!("{:?}", receiver)]))["result"], json!("0x5"));
+
+        // A replay is rejected, and a corrupted receipt location fails the request alone.
+        assert_eq!(request("eth_sendRawTransaction", json!([raw]))["error"]["code"], json!(-32000));
+        let gas = U256::from(u64::MAX) + 1;
+        let overflow = SignedTransaction::sign(U256::one(), U256::one(), gas, Some(receiver), U256::zero(), Vec::new(), 1, &secret);
+        let overflow = format!("0x{}", hex::encode(rlp::encode(&overflow)));
+        assert_eq!(request("eth_sendRawTransaction", json!([overflow]))["error"]["code"], json!(-32602));
+        let mut transaction = database.transaction();
+        transaction.put(state::COL_RECEIPT, tx.hash.as_bytes(), &[0xff]);
+        database.write(transaction).unwrap();
+        assert_eq!(request("eth_getTransactionReceipt", json!([hash]))["error"]["code"], json!(-32603));
+        assert_eq!(request("eth_chainId", json!([]))["result"], json!("0x1"));
+    }
+
+    #[test]
+    fn test_block_parameter() {
+        let database = TempDatabase::new("serve-block");
+        let cli = Cli::from_iter(&["bloom-evm"]);
+        let config = Config::istanbul();
+        let address = H160::from_low_u64_be(0xa);
+        let vicinity = cli.vicinity.vicinity();
+        // Blocks 0 and 1 seal the account with a balance of 100 and then 200.
+        for (count, balance) in [(0u64, 100u64), (1, 200)].iter() {
+            let (_, root) = super::super::head(&database);
+            let mut backend = super::super::open_state(&database, Algorithm::Archive, root, &vicinity).unwrap();
+            backend.apply(vec![account(address, *balance, None, &[])], Vec::new(), false);
+            super::super::commit(&database, Algorithm::Archive, &mut backend, U256::from(*count), &[]);
+            block_cmd::mine(&database, U256::from(count + 1), &vicinity);
+        }
+
+        let server = Server { cli: &cli, database: &database, algorithm: Algorithm::Archive, config: &config };
+        let balance = |block: &str| -> Value {
+            let body = json!({"jsonrpc": "2.0", "id": 1, "method": "eth_getBalance", "params": [format!("{:?}", address), block]});
+            serde_json::from_str(&server.respond(body.to_string().as_bytes())).unwrap()
+        };
+        assert_eq!(balance("latest")["result"], json!("0xc8"));
+        assert_eq!(balance("0x1")["result"], json!("0xc8"));
+        assert_eq!(balance("0x0")["result"], json!("0x64"));
+        assert_eq!(balance("earliest")["result"], json!("0x64"));
+        assert_eq!(balance("0x5")["error"]["code"], json!(-32602));
+
+        // A block whose state is missing fails the request alone.
+        let mut transaction = database.transaction();
+        let mut commit = [0u8; 32];
+        U256::from(3).to_big_endian(&mut commit);
+        transaction.put(state::COL_BLOCK, &commit[..], H256::repeat_byte(9).as_bytes());
+        database.write(transaction).unwrap();
+        block_cmd::mine(&database, U256::from(3), &vicinity);
+        assert_eq!(balance("0x2")["error"]["code"], json!(-32000));
+        assert_eq!(balance("latest")["result"], json!("0xc8"));
+    }
+
+    #[test]
+    fn test_quantity() {
+        assert_eq!(quantity(U256::zero()), json!("0x0"));
+        assert_eq!(quantity(0x5236u64), json!("0x5236"));
+        assert_eq!(number(&json!("0x5236")).ok(), Some(U256::from(0x5236)));
+        assert!(number(&json!("0x")).is_err());
+        assert!(number(&json!("5236")).is_err());
+        assert!(number(&json!(5236)).is_err());
+    }
+
+    #[test]
+    fn test_log_filter() {
+        let log = json!({
+            "address": "0x393fb85c28c24868d5dfce91194699ac342ffa6b",
+            "topics": ["0x11", "0x22"],
+        });
+        let filter = |addresses: Vec<&str>, topics: Vec<Vec<&str>>| LogFilter {
+            from_block: U256::zero(),
+            to_block: U256::zero(),
+            addresses: addresses.into_iter().map(|a| json!(a)).collect(),
+            topics: topics.into_iter().map(|t| t.into_iter().map(|t| json!(t)).collect()).collect(),
+        };
+
+        assert!(filter(vec![], vec![]).matches(&log));
+        assert!(filter(vec!["0x393fb85c28c24868d5dfce91194699ac342ffa6b"], vec![]).matches(&log));
+        assert!(!filter(vec!["0x0000000000000000000000000000000000000001"], vec![]).matches(&log));
+        assert!(filter(vec![], vec![vec![], vec!["0x33", "0x22"]]).matches(&log));
+        assert!(!filter(vec![], vec![vec!["0x22"]]).matches(&log));
+        assert!(!filter(vec![], vec![vec![], vec![], vec!["0x33"]]).matches(&log));
+    }
+}
@@ -1,10 +1,27 @@
 use structopt::StructOpt;
 use kvdb_rocksdb::{Database};
 use bloom_state as state;
-use ethereum_types::{U256,H256};
+use ethereum_types::{U256,H256,H160};
+use journaldb::Algorithm;
+use state::{BackendVicinity, Receipt, State};
+use state::units::parse_amount;
+use evm::backend::{Apply, ApplyBackend, Backend, Basic};
+use serde_json::{json, Value};
 
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::process;
+use std::str::FromStr;
 use std::sync::Arc;
 
+use super::block_cmd;
+
+// ./target/debug/bloom-evm state history
+// ./target/debug/bloom-evm state rewind --to 3
+// ./target/debug/bloom-evm state diff 3 0x28a43525d8d0e2987c0dab24b675fb1c729055e2c3b20c898247d8b9d31abfb8 --format json
+// ./target/debug/bloom-evm state export --format json > genesis.json
+// ./target/debug/bloom-evm --data-dir fresh-db state import genesis.json
+
 
 #[derive(Debug, StructOpt, Clone)]
 pub struct StateCmd {
@@ -15,11 +32,63 @@ pub struct StateCmd {
 #[derive(StructOpt,Debug,Clone)]
 enum Command {
     History{},
+
+    /// Move the head of an archive database back to an earlier commit, removing the later commits with their receipts and blocks
+    Rewind {
+        /// The commit number of the new head
+        #[structopt(long = "to")]
+        to: String,
+    },
+
+    /// Show the accounts and storage slots which differ between two states
+    Diff {
+        /// The commit number or the state root of the first state
+        from: String,
+
+        /// The commit number or the state root of the second state
+        to: String,
+
+        /// The output format, `text` or `json`
+        #[structopt(long = "format", default_value = "text")]
+        format: Format,
+    },
+
+    /// Dump every account with its balance, nonce, code and storage, as a geth genesis `alloc`
+    Export {
+        /// The output format, `json` for a genesis file or `text`
+        #[structopt(long = "format", default_value = "json")]
+        format: Format,
+    },
+
+    /// Create the accounts of the `alloc` of a geth genesis file in one commit
+    Import {
+        /// The genesis JSON file, or a file with only its `alloc`
+        file: String,
+    },
+}
+
+/// Output format of a state command.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Text,
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Format::Text),
+            "json" => Ok(Format::Json),
+            _ => Err(format!("Unknown format {}, expected text or json", s)),
+        }
+    }
 }
 
 impl StateCmd {
-    pub fn run(&self, db: Arc<Database>,count: U256) -> bool{
-        match self.cmd {
+    pub fn run(&self, db: Arc<Database>,count: U256, algorithm: Algorithm, backend: &mut State) -> bool{
+        match &self.cmd {
             Command::History {} => {
                 //println!("count={:?}",count);
                 let total_count = count.as_u32();
@@ -41,7 +110,452 @@ impl StateCmd {
                     l -=  1;
                 }
             }
+
+            Command::Rewind {to} => {
+                let to = U256::from_dec_str(to).expect("--to argument must be a valid commit number");
+                if let Err(e) = rewind(&db, count, algorithm, to) {
+                    eprintln!("{}", e);
+                    process::exit(1);
+                }
+                println!("Rewound to commit {}, removed {} commits", to, count - to);
+            }
+
+            Command::Diff {from, to, format} => {
+                let vicinity = BackendVicinity::default();
+                let open = |side: &str, at: &str| super::state_at(&db, count, at)
+                    .and_then(|root| super::open_state(&db, algorithm, root, &vicinity))
+                    .unwrap_or_else(|e| {
+                        eprintln!("Invalid {} state: {}", side, e);
+                        process::exit(1);
+                    });
+                let before = open("from", from);
+                let after = open("to", to);
+                let diffs = diff(&before, &after);
+
+                match format {
+                    Format::Json => {
+                        let diffs: Vec<Value> = diffs.iter().map(AccountDiff::to_json).collect();
+                        println!("{}", serde_json::to_string_pretty(&diffs).unwrap());
+                    },
+                    Format::Text => {
+                        for account in &diffs {
+                            print!("{}", account);
+                        }
+                        println!("{} accounts changed", diffs.len());
+                    },
+                }
+            }
+
+            Command::Export {format} => {
+                let mut alloc = serde_json::Map::new();
+                for address in backend.list_address() {
+                    let account = backend.get_account(address);
+                    let code = backend.code(address);
+                    let storage = backend.get_storage(address, account.storage_root());
+                    match format {
+                        Format::Json => {
+                            // Like geth, the fields with their default value are left out.
+                            let mut entry = serde_json::Map::new();
+                            entry.insert("balance".into(), json!(format!("{:#x}", account.balance())));
+                            if !account.nonce().is_zero() {
+                                entry.insert("nonce".into(), json!(format!("{:#x}", account.nonce())));
+                            }
+                            if !code.is_empty() {
+                                entry.insert("code".into(), json!(format!("0x{}", hex::encode(&code))));
+                            }
+                            if !storage.is_empty() {
+                                let storage: serde_json::Map<String, Value> = storage.iter()
+                                    .map(|(key, value)| (format!("{:?}", key), json!(format!("{:?}", value))))
+                                    .collect();
+                                entry.insert("storage".into(), Value::Object(storage));
+                            }
+                            alloc.insert(format!("{:?}", address), Value::Object(entry));
+                        },
+                        Format::Text => {
+                            println!("{:?}: {}, code: {} bytes", address, account, code.len());
+                            for (key, value) in &storage {
+                                println!("  {:?}: {:?}", key, value);
+                            }
+                        },
+                    }
+                }
+                if *format == Format::Json {
+                    println!("{}", serde_json::to_string_pretty(&json!({ "alloc": alloc })).unwrap());
+                }
+            }
+
+            Command::Import {file} => {
+                let f = File::open(file).expect("Genesis file not found");
+                let genesis: Value = serde_json::from_reader(f).expect("Genesis file must be valid JSON");
+                let alloc = genesis.get("alloc").unwrap_or(&genesis).as_object().expect("Genesis alloc must be an object");
+
+                let mut values = Vec::new();
+                for (address, entry) in alloc {
+                    values.push(genesis_account(address, entry).unwrap_or_else(|e| panic!("Genesis account {} is invalid: {}", address, e)));
+                }
+                backend.apply(values, Vec::new(), false);
+                println!("Imported {} accounts", alloc.len());
+                return true;
+            }
         }
         false
     }
-}
\ No newline at end of file
+}
+
+/// Move the head back to commit `to`, removing the later commits with their receipts, blocks and
+/// contract ABIs.
+fn rewind(db: &Database, count: U256, algorithm: Algorithm, to: U256) -> Result<(), String> {
+    if to > count {
+        return Err(format!("Commit {} is after the latest commit {}", to, count));
+    }
+    // The journal of a pruning database cannot be undone, its later commits would reuse the eras of the removed ones.
+    if algorithm != Algorithm::Archive {
+        return Err(format!("Only an `archive` database can be rewound, this one is `{}`", algorithm));
+    }
+
+    let mut transaction = db.transaction();
+    let mut commit = to + 1;
+    while commit <= count {
+        let mut arr = [0u8;32];
+        commit.to_big_endian(&mut arr);
+        transaction.delete(state::COL_BLOCK, &arr[..]);
+        if let Some(v) = db.get(state::COL_RECEIPT, &arr[..]).unwrap() {
+            for receipt in rlp::decode_list::<Receipt>(&v) {
+                transaction.delete(state::COL_RECEIPT, receipt.transaction_hash.as_bytes());
+                // The contracts created by the removed commits no longer exist.
+                if let Some(address) = receipt.contract_address {
+                    transaction.delete(state::COL_ABI, address.as_bytes());
+                }
+            }
+            transaction.delete(state::COL_RECEIPT, &arr[..]);
+        }
+        commit = commit + 1;
+    }
+
+    // Blocks sealing a removed commit are removed too.
+    let mut latest = block_cmd::latest_block(db);
+    while let Some(block) = latest.as_ref().filter(|block| block.commit > to) {
+        let mut number = [0u8;32];
+        block.header.number.to_big_endian(&mut number);
+        transaction.delete(state::COL_HEADER, &number[..]);
+        transaction.delete(state::COL_HEADER, block.header.hash().as_bytes());
+        latest = match block.header.number.is_zero() {
+            true => None,
+            false => block_cmd::block_at(db, block.header.number - 1),
+        };
+    }
+    match &latest {
+        Some(block) => {
+            let mut number = [0u8;32];
+            block.header.number.to_big_endian(&mut number);
+            transaction.put(state::COL_HEADER, b"latest", &number[..]);
+        },
+        None => transaction.delete(state::COL_HEADER, b"latest"),
+    }
+    // The timestamp of the pending block goes with its last transaction.
+    let pending = latest.as_ref().map_or(U256::zero(), |block| block.commit);
+    if block_cmd::receipts_between(db, pending, to).is_empty() {
+        transaction.delete(state::COL_HEADER, b"pending-timestamp");
+    }
+
+    let mut arr = [0u8;32];
+    to.to_big_endian(&mut arr);
+    transaction.put(state::COL_BLOCK, b"root-count", &arr[..]);
+    db.write(transaction).unwrap();
+    Ok(())
+}
+
+/// Account of a geth genesis `alloc` entry, whose numbers are hex or decimal strings.
+fn genesis_account(address: &str, entry: &Value) -> Result<Apply<BTreeMap<H256, H256>>, String> {
+    let address = H160::from_str(address.trim_start_matches("0x")).map_err(|e| format!("invalid address: {}", e))?;
+    let number = |name: &str| match entry.get(name) {
+        None => Ok(U256::zero()),
+        Some(Value::String(v)) => parse_amount(v),
+        Some(Value::Number(v)) => U256::from_dec_str(&v.to_string()).map_err(|_| format!("invalid {} {}", name, v)),
+        Some(v) => Err(format!("invalid {} {}", name, v)),
+    };
+    let hex = |v: &Value| -> Result<Vec<u8>, String> {
+        let v = v.as_str().ok_or_else(|| format!("expected a hex string, got {}", v))?;
+        hex::decode(v.trim_start_matches("0x")).map_err(|e| format!("invalid hex {}: {}", v, e))
+    };
+    let word = |v: &Value| -> Result<H256, String> {
+        let bytes = hex(v)?;
+        if bytes.len() > 32 {
+            return Err(format!("storage word {} is longer than 32 bytes", v));
+        }
+        // Shorter words are left padded, as numbers.
+        let mut word = H256::zero();
+        word.as_bytes_mut()[32 - bytes.len()..].copy_from_slice(&bytes);
+        Ok(word)
+    };
+
+    let code = match entry.get("code") {
+        Some(code) => Some(hex(code)?).filter(|code| !code.is_empty()),
+        None => None,
+    };
+    let mut storage = BTreeMap::new();
+    if let Some(slots) = entry.get("storage") {
+        for (key, value) in slots.as_object().ok_or("storage must be an object")? {
+            storage.insert(word(&json!(key))?, word(value)?);
+        }
+    }
+
+    Ok(Apply::Modify {
+        address,
+        basic: Basic { balance: number("balance")?, nonce: number("nonce")? },
+        code,
+        storage,
+        reset_storage: false,
+    })
+}
+
+/// Fields of an account compared by a state diff.
+#[derive(Debug, Clone, PartialEq)]
+struct AccountFields {
+    balance: U256,
+    nonce: U256,
+    code_hash: H256,
+}
+
+/// An account which differs between two states, `None` on the side where it does not exist.
+#[derive(Debug, Clone, PartialEq)]
+struct AccountDiff {
+    address: H160,
+    before: Option<AccountFields>,
+    after: Option<AccountFields>,
+    /// Changed storage slots, with their values before and after, zero for an unset slot.
+    storage: BTreeMap<H256, (H256, H256)>,
+}
+
+/// Compare the accounts of two states, walking the storage of the accounts whose storage root changed.
+fn diff(before: &State, after: &State) -> Vec<AccountDiff> {
+    let before_addresses: BTreeSet<H160> = before.list_address().into_iter().collect();
+    let after_addresses: BTreeSet<H160> = after.list_address().into_iter().collect();
+
+    let mut diffs = Vec::new();
+    for &address in before_addresses.union(&after_addresses) {
+        let a = before_addresses.contains(&address).then(|| before.get_account(address));
+        let b = after_addresses.contains(&address).then(|| after.get_account(address));
+        let fields = |account: &state::Account| AccountFields {
+            balance: *account.balance(),
+            nonce: *account.nonce(),
+            code_hash: account.code_hash(),
+        };
+        let (a_fields, b_fields) = (a.as_ref().map(fields), b.as_ref().map(fields));
+        let a_root = a.as_ref().map(|account| account.storage_root());
+        let b_root = b.as_ref().map(|account| account.storage_root());
+        if a_fields == b_fields && a_root == b_root {
+            continue;
+        }
+
+        let mut storage = BTreeMap::new();
+        if a_root != b_root {
+            let a_storage = a_root.map(|root| before.get_storage(address, root)).unwrap_or_default();
+            let b_storage = b_root.map(|root| after.get_storage(address, root)).unwrap_or_default();
+            for key in a_storage.keys().chain(b_storage.keys()) {
+                let a_value = a_storage.get(key).cloned().unwrap_or_default();
+                let b_value = b_storage.get(key).cloned().unwrap_or_default();
+                if a_value != b_value {
+                    storage.insert(*key, (a_value, b_value));
+                }
+            }
+        }
+
+        diffs.push(AccountDiff { address, before: a_fields, after: b_fields, storage });
+    }
+    diffs
+}
+
+impl AccountDiff {
+    fn change(&self) -> &'static str {
+        match (&self.before, &self.after) {
+            (None, _) => "added",
+            (_, None) => "removed",
+            _ => "modified",
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        let side = |fields: &Option<AccountFields>| fields.as_ref().map(|fields| json!({
+            "balance": format!("{:#x}", fields.balance),
+            "nonce": format!("{:#x}", fields.nonce),
+            "codeHash": format!("{:?}", fields.code_hash),
+        }));
+        let storage: serde_json::Map<String, Value> = self.storage.iter()
+            .map(|(key, (before, after))| (format!("{:?}", key), json!({"from": format!("{:?}", before), "to": format!("{:?}", after)})))
+            .collect();
+
+        json!({
+            "address": format!("{:?}", self.address),
+            "change": self.change(),
+            "from": side(&self.before),
+            "to": side(&self.after),
+            "storage": storage,
+        })
+    }
+}
+
+impl std::fmt::Display for AccountDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(f, "{} {:?}", self.change(), self.address)?;
+        let before = self.before.as_ref();
+        let after = self.after.as_ref();
+        let field = |f: &mut std::fmt::Formatter, name: &str, a: Option<String>, b: Option<String>| {
+            match (a, b) {
+                (Some(a), Some(b)) if a == b => Ok(()),
+                (a, b) => writeln!(f, "  {}: {} -> {}", name, a.unwrap_or_else(|| "-".into()), b.unwrap_or_else(|| "-".into())),
+            }
+        };
+        field(f, "balance", before.map(|a| a.balance.to_string()), after.map(|a| a.balance.to_string()))?;
+        field(f, "nonce", before.map(|a| a.nonce.to_string()), after.map(|a| a.nonce.to_string()))?;
+        field(f, "code hash", before.map(|a| format!("{:?}", a.code_hash)), after.map(|a| format!("{:?}", a.code_hash)))?;
+        for (key, (a, b)) in &self.storage {
+            writeln!(f, "  storage {:?}: {:?} -> {:?}", key, a, b)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{diff, genesis_account, rewind};
+    use std::collections::BTreeMap;
+    use ethereum_types::{H160, H256, U256};
+    use evm::backend::{Apply, ApplyBackend, Backend, Basic};
+    use bloom_state::{self as state, BackendVicinity, Receipt};
+    use serde_json::json;
+    use journaldb::Algorithm;
+
+    use crate::abi;
+    use crate::commands::block_cmd;
+    use crate::test_helpers::{account, memory_database, memory_state, TempDatabase};
+
+    #[test]
+    fn test_diff() {
+        let vicinity = BackendVicinity::default();
+        let database = memory_database();
+        let mut backend = memory_state(&database, H256::zero(), &vicinity);
+
+        let address = H160::from_low_u64_be;
+        backend.apply(vec![
+            account(address(1), 10, None, &[]),
+            account(address(2), 20, None, &[(1, 1), (2, 2)]),
+            account(address(3), 30, None, &[]),
+        ], Vec::new(), false);
+        let root = backend.commit(1, None);
+        let before = memory_state(&database, root, &vicinity);
+        backend.apply(vec![
+            account(address(2), 20, None, &[(2, 3), (4, 4)]),
+            account(address(3), 31, None, &[]),
+            account(address(5), 50, None, &[]),
+        ], Vec::new(), false);
+        backend.apply(vec![Apply::Delete::<BTreeMap<H256, H256>> { address: H160::from_low_u64_be(1) }], Vec::new(), false);
+        backend.commit(2, None);
+
+        let diffs = diff(&before, &backend);
+        let changes: Vec<_> = diffs.iter().map(|d| (d.address.to_low_u64_be(), d.change())).collect();
+        assert_eq!(changes, vec![(1, "removed"), (2, "modified"), (3, "modified"), (5, "added")]);
+
+        assert_eq!(diffs[1].before, diffs[1].after);
+        let storage: Vec<_> = diffs[1].storage.iter()
+            .map(|(k, (a, b))| (k.to_low_u64_be(), a.to_low_u64_be(), b.to_low_u64_be()))
+            .collect();
+        assert_eq!(storage, vec![(2, 2, 3), (4, 0, 4)]);
+
+        assert_eq!(diffs[2].after.as_ref().unwrap().balance, U256::from(31));
+        assert!(diffs[2].storage.is_empty());
+        assert!(diff(&backend, &backend).is_empty());
+    }
+
+    #[test]
+    fn test_genesis_account() {
+        let entry = json!({"balance": "1000000000000000000", "nonce": "0x7", "code": "0x6000", "storage": {"0x01": "0x2a"}});
+        match genesis_account("0000000000000000000000000000000000000abc", &entry).unwrap() {
+            Apply::Modify { address, basic, code, storage, .. } => {
+                assert_eq!(address, H160::from_low_u64_be(0xabc));
+                assert_eq!(basic.balance, U256::exp10(18));
+                assert_eq!(basic.nonce, U256::from(7));
+                assert_eq!(code, Some(vec![0x60, 0x00]));
+                assert_eq!(storage.get(&H256::from_low_u64_be(1)), Some(&H256::from_low_u64_be(0x2a)));
+            },
+            Apply::Delete { .. } => panic!("Genesis account must be created"),
+        }
+
+        match genesis_account("0x0000000000000000000000000000000000000001", &json!({"balance": "0x10", "code": "0x"})).unwrap() {
+            Apply::Modify { basic, code, storage, .. } => {
+                assert_eq!(basic, Basic { balance: U256::from(16), nonce: U256::zero() });
+                assert_eq!(code, None);
+                assert!(storage.is_empty());
+            },
+            Apply::Delete { .. } => panic!("Genesis account must be created"),
+        }
+
+        assert!(genesis_account("zz", &json!({})).is_err());
+        assert!(genesis_account("0x0000000000000000000000000000000000000001", &json!({"balance": true})).is_err());
+        assert!(genesis_account("0x0000000000000000000000000000000000000001", &json!({"storage": {"0x01": format!("0x{}", "00".repeat(33))}})).is_err());
+    }
+
+    #[test]
+    fn test_rewind() {
+        let database = TempDatabase::new("rewind");
+        let vicinity = BackendVicinity { block_timestamp: U256::from(1000), ..Default::default() };
+        let mut backend = super::super::open_state(&database, Algorithm::Archive, H256::zero(), &vicinity).unwrap();
+        let receipt = |n: u8| Receipt::new(H256::repeat_byte(n), true, U256::from(21000), U256::from(21000), Some(H160::repeat_byte(n)), Vec::new());
+        // Commits 1 and 2 are sealed by blocks 0 and 1, commit 3 is pending.
+        for n in 1..=3u8 {
+            super::super::commit(&database, Algorithm::Archive, &mut backend, U256::from(n - 1), &[receipt(n)]);
+            abi::store_abi(&database, H160::repeat_byte(n), &json!([]));
+            if n < 3 {
+                block_cmd::mine(&database, U256::from(n), &vicinity);
+            }
+        }
+        assert!(block_cmd::pending_timestamp(&database).is_some());
+
+        rewind(&database, U256::from(3), Algorithm::Archive, U256::one()).unwrap();
+        let count = database.get(state::COL_BLOCK, b"root-count").unwrap().map(|v| U256::from(v.as_slice()));
+        assert_eq!(count, Some(U256::one()));
+        let key = |n: u64| {
+            let mut arr = [0u8; 32];
+            U256::from(n).to_big_endian(&mut arr);
+            arr
+        };
+        assert!(database.get(state::COL_BLOCK, &key(1)).unwrap().is_some());
+        assert!(database.get(state::COL_BLOCK, &key(2)).unwrap().is_none());
+        assert!(database.get(state::COL_RECEIPT, &key(1)).unwrap().is_some());
+        assert!(database.get(state::COL_RECEIPT, H256::repeat_byte(1).as_bytes()).unwrap().is_some());
+        for n in 2..=3u8 {
+            assert!(database.get(state::COL_RECEIPT, &key(n as u64)).unwrap().is_none());
+            assert!(database.get(state::COL_RECEIPT, H256::repeat_byte(n).as_bytes()).unwrap().is_none());
+            assert!(abi::stored_contract(&database, H160::repeat_byte(n)).is_none());
+        }
+        assert!(abi::stored_contract(&database, H160::repeat_byte(1)).is_some());
+        let latest = block_cmd::latest_block(&database).unwrap();
+        assert_eq!(latest.header.number, U256::zero());
+        assert!(block_cmd::block_at(&database, U256::one()).is_none());
+        assert!(block_cmd::pending_timestamp(&database).is_none());
+
+        assert!(rewind(&database, U256::one(), Algorithm::Archive, U256::from(2)).is_err());
+    }
+
+    #[test]
+    fn test_rewind_pruning() {
+        let database = TempDatabase::new("rewind-pruning");
+        let vicinity = BackendVicinity::default();
+        let algorithm = Algorithm::OverlayRecent;
+        let mut backend = super::super::open_state(&database, algorithm, H256::zero(), &vicinity).unwrap();
+        for n in 0..3u64 {
+            backend.apply(vec![account(H160::repeat_byte(1), n, None, &[])], Vec::new(), false);
+            super::super::commit(&database, algorithm, &mut backend, U256::from(n), &[]);
+        }
+
+        assert!(rewind(&database, U256::from(3), algorithm, U256::one()).is_err());
+        let (count, _) = super::super::head(&database);
+        assert_eq!(count, U256::from(3));
+
+        // The database still commits and reads its state after the refused rewind.
+        backend.apply(vec![account(H160::repeat_byte(1), 3, None, &[])], Vec::new(), false);
+        let root = super::super::commit(&database, algorithm, &mut backend, count, &[]);
+        let state = super::super::open_state(&database, algorithm, root, &vicinity).unwrap();
+        assert_eq!(state.basic(H160::repeat_byte(1)).balance, U256::from(3));
+    }
+}
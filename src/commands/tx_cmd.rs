@@ -0,0 +1,103 @@
+use structopt::StructOpt;
+use evm::backend::Backend;
+use evm::executor::StackExecutor;
+use evm::Config;
+use bloom_state::{State, Receipt};
+use ethereum_types::H160;
+
+use crate::executer::{self, Error, Executed};
+use crate::tracer::TraceOpts;
+use crate::transaction::SignedTransaction;
+
+// ./target/debug/bloom-evm --chain-id 1 tx send-raw 0xf86c098504a817c800825208943535353535353535353535353535353535353535880de0b6b3a7640000...
+
+#[derive(Debug, StructOpt, Clone)]
+pub struct TxCmd {
+    #[structopt(subcommand)]
+    cmd: Command
+}
+
+#[derive(StructOpt, Debug, Clone)]
+enum Command {
+    /// Execute a signed raw transaction, legacy or EIP-155, sent by the account recovered from its signature
+    SendRaw {
+        /// The RLP encoded signed transaction, as hex
+        raw: String,
+
+        #[structopt(flatten)]
+        trace: TraceOpts,
+    },
+}
+
+impl TxCmd {
+    pub fn run(&self, backend: &mut State, config: &Config, receipts: &mut Vec<Receipt>) -> Result<bool, Error> {
+        match &self.cmd {
+            Command::SendRaw {raw, trace} => {
+                let tx = SignedTransaction::from_hex(raw).map_err(Error::InvalidTransaction)?;
+                println!("Transaction sender is {:?}", tx.sender()?);
+                let (contract_address, executed) = execute(&tx, Some(trace), backend, config)?;
+                super::print_gas_used(&executed);
+                let output = executed.record(tx.hash, contract_address, receipts)?;
+
+                match contract_address {
+                    Some(address) => println!("Create contract successful, contract address is {:?}", address),
+                    None => println!("Transaction executed, State OK. result: {:?}", hex::encode(output)),
+                }
+                Ok(true)
+            }
+        }
+    }
+}
+
+/// Execute a signed transaction, without printing anything but its trace. Returns the address of
+/// the created contract, if any, and the execution, whose receipt is left to record.
+pub(super) fn execute(tx: &SignedTransaction, trace: Option<&TraceOpts>, backend: &mut State, config: &Config) -> Result<(Option<H160>, Executed<Vec<u8>>), Error> {
+    if let Some(chain_id) = tx.chain_id() {
+        if backend.chain_id() != chain_id.into() {
+            return Err(Error::InvalidChainId(backend.chain_id(), chain_id));
+        }
+    }
+    let from = tx.sender()?;
+    if tx.gas > u64::MAX.into() {
+        return Err(Error::GasLimitOverflow(tx.gas));
+    }
+    let gas_limit = tx.gas.as_u64();
+    let gas = executer::gas_usize(gas_limit)?;
+
+    let contract_address = match tx.to {
+        Some(_) => None,
+        None => {
+            let executor = StackExecutor::new(backend, gas, config);
+            Some(executor.create_address(evm::CreateScheme::Legacy { caller: from }))
+        },
+    };
+    let logger = trace.and_then(TraceOpts::struct_logger);
+
+    let (executed, calls) = executer::execute_evm_with_calls(
+        matches!(trace, Some(trace) if trace.calls()),
+        from,
+        tx.value,
+        gas_limit,
+        tx.gas_price,
+        Some(tx.nonce),
+        executer::intrinsic_gas(config, tx.to.is_none(), &tx.data),
+        |executor| {
+            if let Some(logger) = &logger {
+                executor.set_tracer(logger.clone());
+            }
+            let (reason, output) = match tx.to {
+                Some(to) => executor.transact_call(from, to, tx.value, tx.data.clone(), gas),
+                None => executor.transact_create(from, tx.value, tx.data.clone(), gas),
+            };
+            (output.clone(), (reason, output))
+        },
+        config,
+        backend
+    )?;
+
+    if let Some(trace) = trace {
+        let result = executed.result.as_ref().map(|output| output.as_slice());
+        trace.write_transaction(logger, calls, executed.gas_used, result);
+    }
+    Ok((contract_address, executed))
+}
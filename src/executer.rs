@@ -1,10 +1,18 @@
-use ethereum_types::{H160, U256};
+use std::cell::RefCell;
+use std::convert::TryFrom;
+use std::fmt;
+use std::rc::Rc;
+use ethereum_types::{H160, H256, U256};
 use evm::executor::StackExecutor;
-use evm::ExitReason;
-use evm::backend::ApplyBackend;
+use evm::{ExitReason, ExitError, ExitRevert, ExitFatal};
+use evm::backend::{ApplyBackend, Backend, Log};
 use evm::Config;
-use evm::Transfer;
-use bloom_state::State;
+use bloom_state::{State, Receipt};
+use rlp::RlpStream;
+use sha3::{Digest, Keccak256};
+
+use crate::tracer::{CallFrame, CallTracer};
+use crate::transaction::MAX_CHAIN_ID;
 
 #[derive(Debug)]
 pub enum Error
@@ -17,16 +25,250 @@ pub enum Error
 	PaymentOverflow,
 	/// Withdraw fee failed
 	WithdrawFailed,
-	/// Gas price is too low.
+	/// Gas price is lower than the base fee of the block.
 	GasPriceTooLow,
+	/// Gas limit is lower than the intrinsic gas of the transaction, with the intrinsic gas and the gas limit
+	IntrinsicGasTooLow(u64, u64),
+	/// Gas limit does not fit the gas counter of the executor
+	GasLimitTooHigh(u64),
+	/// Gas limit of the transaction does not fit a `u64`
+	GasLimitOverflow(U256),
 	/// Call failed
-	ExitReasonFailed,
-	/// Call reverted
-	ExitReasonRevert,
+	ExitReasonFailed(ExitError),
+	/// Call reverted, with the revert data
+	ExitReasonRevert(ExitRevert, Vec<u8>),
 	/// Call returned VM fatal error
-	ExitReasonFatal,
-	/// Nonce is invalid
-	InvalidNonce,
+	ExitReasonFatal(ExitFatal),
+	/// Nonce is lower than the nonce of the sender, with the nonce of the sender and of the transaction
+	NonceTooLow(U256, U256),
+	/// Nonce is higher than the nonce of the sender, with the nonce of the sender and of the transaction
+	NonceTooHigh(U256, U256),
+	/// Created contract address already has code or a nonce
+	AddressCollision(H160),
+	/// Transaction signature is invalid
+	InvalidSignature,
+	/// Transaction is signed for another chain, with the expected and the signed chain id
+	InvalidChainId(U256, u64),
+	/// Raw transaction cannot be decoded, with the reason
+	InvalidTransaction(String),
+	/// CREATE2 deployment on a fork without CREATE2
+	Create2Unsupported,
+	/// Chain id is too high to sign transactions for
+	ChainIdTooHigh(U256),
+}
+
+impl Error {
+	/// Process exit code of the error class: 2 for rejected operations, 3 for failed,
+	/// 4 for reverted and 5 for fatal executions.
+	pub fn exit_code(&self) -> i32 {
+		match self {
+			Error::BalanceLow |
+			Error::FeeOverflow |
+			Error::PaymentOverflow |
+			Error::WithdrawFailed |
+			Error::GasPriceTooLow |
+			Error::IntrinsicGasTooLow(..) |
+			Error::GasLimitTooHigh(_) |
+			Error::GasLimitOverflow(_) |
+			Error::NonceTooLow(..) |
+			Error::NonceTooHigh(..) |
+			Error::AddressCollision(_) |
+			Error::InvalidSignature |
+			Error::InvalidChainId(..) |
+			Error::InvalidTransaction(_) |
+			Error::Create2Unsupported |
+			Error::ChainIdTooHigh(_) => 2,
+			Error::ExitReasonFailed(_) => 3,
+			Error::ExitReasonRevert(..) => 4,
+			Error::ExitReasonFatal(_) => 5,
+		}
+	}
+}
+
+impl fmt::Display for Error {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Error::BalanceLow => write!(f, "Not enough balance to perform action"),
+			Error::FeeOverflow => write!(f, "Calculating total fee overflowed"),
+			Error::PaymentOverflow => write!(f, "Calculating total payment overflowed"),
+			Error::WithdrawFailed => write!(f, "Withdraw fee failed"),
+			Error::GasPriceTooLow => write!(f, "Gas price is lower than the base fee of the block"),
+			Error::IntrinsicGasTooLow(intrinsic, gas_limit) => write!(f, "Gas limit {} is lower than the intrinsic gas {} of the transaction", gas_limit, intrinsic),
+			Error::GasLimitTooHigh(gas_limit) => write!(f, "Gas limit {} is too high for the executor", gas_limit),
+			Error::GasLimitOverflow(gas_limit) => write!(f, "Gas limit {} of the transaction does not fit 64 bits", gas_limit),
+			Error::ExitReasonFailed(e) => write!(f, "Execution failed: {:?}", e),
+			Error::ExitReasonRevert(_, data) if data.is_empty() => write!(f, "Execution reverted"),
+			Error::ExitReasonRevert(_, data) => write!(f, "Execution reverted: {}", revert_reason(data)),
+			Error::ExitReasonFatal(e) => write!(f, "Execution fatal error: {:?}", e),
+			Error::NonceTooLow(expected, nonce) => write!(f, "Nonce {} is too low, the next nonce of the sender is {}", nonce, expected),
+			Error::NonceTooHigh(expected, nonce) => write!(f, "Nonce {} is too high, the next nonce of the sender is {}", nonce, expected),
+			Error::AddressCollision(address) => write!(f, "Contract address {:?} already has code or a nonce", address),
+			Error::InvalidSignature => write!(f, "Transaction signature is invalid"),
+			Error::InvalidChainId(expected, chain_id) => write!(f, "Transaction is signed for chain id {}, expected {}", chain_id, expected),
+			Error::InvalidTransaction(reason) => write!(f, "{}", reason),
+			Error::Create2Unsupported => write!(f, "--salt needs CREATE2, which forks before constantinople do not have"),
+			Error::ChainIdTooHigh(chain_id) => write!(f, "Chain id {} is too high, the highest is {}", chain_id, MAX_CHAIN_ID),
+		}
+	}
+}
+
+impl std::error::Error for Error {}
+
+/// Selector of the `Error(string)` revert data, emitted by `require` and `revert`.
+const ERROR_SELECTOR: [u8; 4] = [0x08, 0xc3, 0x79, 0xa0];
+/// Selector of the `Panic(uint256)` revert data, emitted by failing `assert` and checked arithmetic.
+const PANIC_SELECTOR: [u8; 4] = [0x4e, 0x48, 0x7b, 0x71];
+
+/// Decode the revert data of an execution into a readable reason.
+///
+/// `Error(string)` yields its message and `Panic(uint256)` its code, any other data is shown as hex.
+pub fn revert_reason(data: &[u8]) -> String {
+	if data.len() >= 4 && data[..4] == ERROR_SELECTOR {
+		if let Some(message) = decode_abi_string(&data[4..]) {
+			return message;
+		}
+	} else if data.len() == 36 && data[..4] == PANIC_SELECTOR {
+		let code = U256::from_big_endian(&data[4..]);
+		return match panic_description(code) {
+			Some(description) => format!("Panic(0x{:x}): {}", code, description),
+			None => format!("Panic(0x{:x})", code),
+		};
+	}
+	format!("0x{}", hex::encode(data))
+}
+
+/// Decode an ABI encoded `string` which is the single parameter of the data.
+fn decode_abi_string(data: &[u8]) -> Option<String> {
+	let word = |at: usize| -> Option<usize> {
+		let word = data.get(at..at.checked_add(32)?)?;
+		if word[..24].iter().any(|b| *b != 0) {
+			return None;
+		}
+		let mut arr = [0u8; 8];
+		arr.copy_from_slice(&word[24..]);
+		Some(u64::from_be_bytes(arr) as usize)
+	};
+	let offset = word(0)?;
+	let len = word(offset)?;
+	let start = offset.checked_add(32)?;
+	let bytes = data.get(start..start.checked_add(len)?)?;
+	String::from_utf8(bytes.to_vec()).ok()
+}
+
+/// Meaning of the Solidity panic codes.
+fn panic_description(code: U256) -> Option<&'static str> {
+	if code > U256::from(0xffu64) {
+		return None;
+	}
+	match code.low_u64() {
+		0x00 => Some("generic compiler panic"),
+		0x01 => Some("assertion failed"),
+		0x11 => Some("arithmetic overflow or underflow"),
+		0x12 => Some("division or modulo by zero"),
+		0x21 => Some("invalid enum value"),
+		0x22 => Some("invalid storage byte array encoding"),
+		0x31 => Some("pop on empty array"),
+		0x32 => Some("array index out of bounds"),
+		0x41 => Some("out of memory"),
+		0x51 => Some("call to zero-initialized function"),
+		_ => None,
+	}
+}
+
+/// Check the exit reason of an execution, returning its output when it succeeded.
+pub fn exit_result(reason: ExitReason, output: Vec<u8>) -> Result<Vec<u8>, Error> {
+	match reason {
+		ExitReason::Succeed(_) => Ok(output),
+		ExitReason::Error(e) => Err(Error::ExitReasonFailed(e)),
+		ExitReason::Revert(e) => Err(Error::ExitReasonRevert(e, output)),
+		ExitReason::Fatal(e) => Err(Error::ExitReasonFatal(e)),
+	}
+}
+
+/// Outcome of a transaction which passed validation and was executed.
+pub struct Executed<R> {
+	/// Value of the operation, or the error the execution ended with.
+	pub result: Result<R, Error>,
+	/// Gas used by the transaction, after refunds.
+	pub gas_used: U256,
+	/// Part of the gas used which is the intrinsic gas of the transaction.
+	pub intrinsic_gas: U256,
+	/// Logs emitted by the transaction.
+	pub logs: Vec<Log>,
+}
+
+impl<R> Executed<R> {
+	/// Append the receipt of the transaction to the receipts of the pending commit, returning
+	/// the result of the execution.
+	pub fn record(
+		self,
+		transaction_hash: H256,
+		contract_address: Option<H160>,
+		receipts: &mut Vec<Receipt>,
+	) -> Result<R, Error> {
+		let cumulative_gas_used = receipts.last()
+			.map_or(U256::zero(), |r| r.cumulative_gas_used) + self.gas_used;
+		receipts.push(Receipt::new(
+			transaction_hash,
+			self.result.is_ok(),
+			self.gas_used,
+			cumulative_gas_used,
+			contract_address.filter(|_| self.result.is_ok()),
+			self.logs,
+		));
+		self.result
+	}
+}
+
+/// Parse a gas amount, decimal or hex with a 0x prefix.
+pub fn parse_gas(s: &str) -> Result<u64, String> {
+	let gas = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+		Some(hex) => u64::from_str_radix(hex, 16),
+		None => s.parse(),
+	};
+	gas.map_err(|e| format!("Invalid gas {}: {}", s, e))
+}
+
+/// Gas limit as the gas counter of the executor, which is narrower than `u64` on 32 bit targets.
+pub fn gas_usize(gas_limit: u64) -> Result<usize, Error> {
+	usize::try_from(gas_limit).map_err(|_| Error::GasLimitTooHigh(gas_limit))
+}
+
+/// Gas charged before executing a transaction: the base cost of a call or a contract creation and
+/// the cost of its input data, as scheduled by the fork.
+pub fn intrinsic_gas(config: &Config, create: bool, data: &[u8]) -> u64 {
+	let zero_data_len = data.iter().filter(|b| **b == 0).count();
+	let non_zero_data_len = data.len() - zero_data_len;
+	let base = match create {
+		true => config.gas_transaction_create,
+		false => config.gas_transaction_call,
+	};
+	(base + zero_data_len * config.gas_transaction_zero_data + non_zero_data_len * config.gas_transaction_non_zero_data) as u64
+}
+
+/// Hash of an unsigned transaction, the keccak of its RLP encoding followed by its sender. Without
+/// a signature, the sender tells apart the same transaction sent by two accounts.
+pub fn transaction_hash(
+	from: H160,
+	nonce: U256,
+	gas_price: U256,
+	gas_limit: u64,
+	to: Option<H160>,
+	value: U256,
+	data: &[u8],
+) -> H256 {
+	let mut stream = RlpStream::new_list(7);
+	stream.append(&nonce);
+	stream.append(&gas_price);
+	stream.append(&gas_limit);
+	match to {
+		Some(ref to) => { stream.append(to); },
+		None => { stream.append_empty_data(); },
+	}
+	stream.append(&value);
+	stream.append(&data);
+	stream.append(&from);
+	H256::from_slice(Keccak256::digest(&stream.out()).as_slice())
 }
 
 // /// Check whether an account is empty.
@@ -55,19 +297,26 @@ pub enum Error
 // }
 
 /// Execute an EVM operation.
+///
+/// Returns an error without touching the state when the transaction cannot be paid for, its gas
+/// limit does not cover its `intrinsic_gas` or its nonce, if given, is not the nonce of the
+/// sender. Otherwise the state is updated with the fee and the outcome of the execution, and the
+/// nonce of the sender is incremented once. The fee of the used gas goes to the coinbase, less the
+/// base fee of the block which is burnt.
+#[allow(clippy::too_many_arguments)]
 pub fn execute_evm<F, R>(
 	source: H160,
 	value: U256,
-	gas_limit: u32,
+	gas_limit: u64,
 	gas_price: U256,
 	nonce: Option<U256>,
+	intrinsic_gas: u64,
 	f: F,
+	config: &Config,
 	backend: & mut State
-) -> Result<R, Error> where
-	F: FnOnce(&mut StackExecutor<State>) -> (R, ExitReason),
+) -> Result<Executed<R>, Error> where
+	F: FnOnce(&mut StackExecutor<State>) -> (R, (ExitReason, Vec<u8>)),
 {
-	assert!(gas_price >= U256::zero(), Error::GasPriceTooLow);
-
 //	let vicinity = Vicinity {
 //		gas_price: U256::zero(),
 //		origin: H160::zero(),
@@ -81,78 +330,465 @@ pub fn execute_evm<F, R>(
 //	};
 //	let state = BTreeMap::<H160, Account>::new();
 //	let mut backend = Backend::new(&vicinity, state);
-	let config = Config::istanbul();
+	backend.set_transaction(source, gas_price);
+	let coinbase = backend.block_coinbase();
+	let base_fee = backend.block_base_fee();
+	if gas_price < base_fee {
+		return Err(Error::GasPriceTooLow);
+	}
+
 	let mut executor = StackExecutor::new(
 		backend,
-		gas_limit as usize,
-		&config,
+		gas_usize(gas_limit)?,
+		config,
 	);
 
 	let total_fee = gas_price.checked_mul(U256::from(gas_limit)).ok_or(Error::FeeOverflow)?;
 	let total_payment = value.checked_add(total_fee).ok_or(Error::PaymentOverflow)?;
 	let state_account = executor.account_mut(source.clone());
 	let source_account = state_account.basic.clone();
-	assert!(source_account.balance >= total_payment, Error::BalanceLow);
+	if source_account.balance < total_payment {
+		return Err(Error::BalanceLow);
+	}
+	match nonce {
+		Some(nonce) if nonce < source_account.nonce => return Err(Error::NonceTooLow(source_account.nonce, nonce)),
+		Some(nonce) if nonce > source_account.nonce => return Err(Error::NonceTooHigh(source_account.nonce, nonce)),
+		_ => (),
+	}
+	if gas_limit < intrinsic_gas {
+		return Err(Error::IntrinsicGasTooLow(intrinsic_gas, gas_limit));
+	}
 	executor.withdraw(source.clone(), total_fee).map_err(|_| Error::WithdrawFailed)?;
 
-	if let Some(nonce) = nonce {
-		assert!(source_account.nonce == nonce, Error::InvalidNonce);
-	}
+	let (retv, (reason, output)) = f(&mut executor);
 
-	let (retv, reason) = f(&mut executor);
+	// The transaction uses the nonce once whatever its outcome, even when the executor stopped
+	// before incrementing it.
+	executor.account_mut(source).basic.nonce = source_account.nonce + U256::one();
 
-	let ret = match reason {
-		ExitReason::Succeed(_) => Ok(retv),
-		ExitReason::Error(_) => Err(Error::ExitReasonFailed),
-		ExitReason::Revert(_) => Err(Error::ExitReasonRevert),
-		ExitReason::Fatal(_) => Err(Error::ExitReasonFatal),
-	};
+	let result = exit_result(reason, output).map(|_| retv);
 
+	let gas_used = U256::from(executor.used_gas());
 	let actual_fee = executor.fee(gas_price);
 	executor.deposit(source, total_fee.saturating_sub(actual_fee));
+	executor.deposit(coinbase, executor.fee(gas_price - base_fee));
 
 	let (values, logs) = executor.deconstruct();
-	backend.apply(values, logs, true);
+	let logs: Vec<Log> = logs.into_iter().collect();
+	backend.apply(values, logs.clone(), true);
 
 	//println!("{:?}", &backend);
 
-	ret
+	Ok(Executed {
+		result,
+		gas_used,
+		intrinsic_gas: intrinsic_gas.into(),
+		logs,
+	})
 }
 
-/// Execute an transfer operation.
-pub fn execute_transfer(
-	from: H160,
-	to: H160,
+/// Execute an EVM operation like `execute_evm`, also returning the tree of its message calls when
+/// `calls` is set.
+///
+/// The tree is `None` when the transaction was rejected before entering its first frame.
+#[allow(clippy::too_many_arguments)]
+pub fn execute_evm_with_calls<F, R>(
+	calls: bool,
+	source: H160,
 	value: U256,
-	gas_limit: u32,
+	gas_limit: u64,
 	gas_price: U256,
+	nonce: Option<U256>,
+	intrinsic_gas: u64,
+	f: F,
+	config: &Config,
 	backend: & mut State
-) -> Result<(), Error>
+) -> Result<(Executed<R>, Option<CallFrame>), Error> where
+	F: FnOnce(&mut StackExecutor<State>) -> (R, (ExitReason, Vec<u8>)),
 {
-	assert!(gas_price >= U256::zero(), Error::GasPriceTooLow);
-
-	let config = Config::istanbul();
-	let mut executor = StackExecutor::new(
+	let tracer = Rc::new(RefCell::new(CallTracer::default()));
+	let executed = execute_evm(
+		source,
+		value,
+		gas_limit,
+		gas_price,
+		nonce,
+		intrinsic_gas,
+		|executor| {
+			if calls {
+				executor.set_tracer(tracer.clone());
+			}
+			f(executor)
+		},
+		config,
 		backend,
-		gas_limit as usize,
-		&config,
-	);
+	)?;
+	let calls = tracer.take().into_root();
+	Ok((executed, calls))
+}
 
-	let total_fee = gas_price.checked_mul(U256::from(gas_limit)).ok_or(Error::FeeOverflow)?;
-	let total_payment = value.checked_add(total_fee).ok_or(Error::PaymentOverflow)?;
-	let state_account = executor.account_mut(from.clone());
-	assert!(state_account.basic.balance >= total_payment, Error::BalanceLow);
-	state_account.basic.nonce += U256::one();
-
-	executor.withdraw(from.clone(), total_fee).map_err(|_| Error::WithdrawFailed)?;
-	executor.transfer(Transfer{
-		source: from,
-		target: to,
-		value,
-	}).unwrap();
+/// Find the minimal gas limit, up to `cap`, with which a transaction succeeds.
+///
+/// `execute` runs the transaction with the given gas limit on a scratch state. Returns the limit
+/// and the gas used with it, or the error of the transaction when it fails even with `cap`.
+pub fn estimate_gas<F>(cap: u64, mut execute: F) -> Result<(u64, U256), Error> where
+	F: FnMut(u64) -> Result<Executed<()>, Error>,
+{
+	let executed = execute(cap)?;
+	executed.result?;
 
-	let (values, logs) = executor.deconstruct();
-	backend.apply(values, logs, true);
+	// The gas used after refunds is a lower bound of the gas limit needed.
+	let mut gas_used = executed.gas_used;
+	let mut lo = gas_used.low_u64().saturating_sub(1);
+	let mut hi = cap;
+	while hi - lo > 1 {
+		let mid = lo + (hi - lo) / 2;
+		match execute(mid) {
+			Ok(Executed { result: Ok(()), gas_used: used, .. }) => {
+				hi = mid;
+				gas_used = used;
+			},
+			_ => lo = mid,
+		}
+	}
+	Ok((hi, gas_used))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{revert_reason, estimate_gas, execute_evm, execute_evm_with_calls, intrinsic_gas, parse_gas, transaction_hash, Error, Executed};
+	use ethereum_types::{H160, H256, U256};
+	use evm::backend::{ApplyBackend, Backend};
+	use evm::executor::CallType;
+	use evm::{Config, ExitError};
+	use bloom_state::{BackendVicinity, State};
+	use crate::test_helpers::{account, memory_database, memory_state};
+
+	#[test]
+	fn test_execute_evm_with_calls() {
+		let vicinity = BackendVicinity::default();
+		let database = memory_database();
+		let mut backend = memory_state(&database, H256::zero(), &vicinity);
+
+		let caller = H160::from_low_u64_be(1);
+		let outer = H160::from_low_u64_be(0xa);
+		let inner = H160::from_low_u64_be(0xb);
+		// Calls `inner` with all the gas left and returns its output.
+		let outer_code = hex::decode("6020600060006000600073000000000000000000000000000000000000000b5af160206000f3").unwrap();
+		// Returns 0x2a.
+		let inner_code = hex::decode("602a60005260206000f3").unwrap();
+
+		backend.apply(vec![
+			account(caller, 0, None, &[]),
+			account(outer, 0, Some(outer_code), &[]),
+			account(inner, 0, Some(inner_code), &[]),
+		], Vec::new(), false);
+
+		let (executed, calls) = execute_evm_with_calls(
+			true,
+			caller,
+			U256::zero(),
+			100000,
+			U256::zero(),
+			None,
+			21000,
+			|executor| ((), executor.transact_call(caller, outer, U256::zero(), Vec::new(), 100000)),
+			&Config::istanbul(),
+			&mut backend,
+		).unwrap();
+		assert!(executed.result.is_ok());
+
+		let calls = calls.unwrap();
+		assert_eq!(calls.call_type, CallType::Call);
+		assert_eq!((calls.from, calls.to), (caller, outer));
+		assert_eq!(calls.calls.len(), 1);
+		assert_eq!(calls.calls[0].call_type, CallType::Call);
+		assert_eq!((calls.calls[0].from, calls.calls[0].to), (outer, inner));
+		assert_eq!(U256::from(calls.calls[0].output.as_slice()), U256::from(0x2a));
+		assert_eq!(calls.output, calls.calls[0].output);
+		assert!(calls.gas_used > calls.calls[0].gas_used);
+	}
+
+	#[test]
+	fn test_failed_calls() {
+		let vicinity = BackendVicinity::default();
+		let database = memory_database();
+		let mut backend = memory_state(&database, H256::zero(), &vicinity);
+
+		let caller = H160::from_low_u64_be(1);
+		let outer = H160::from_low_u64_be(0xa);
+		let inner = H160::from_low_u64_be(0xb);
+		// Without any balance, calls `inner` with a value of 1 then creates a contract with a
+		// value of 1.
+		let outer_code = hex::decode("6000600060006000600173000000000000000000000000000000000000000b5af150600060006001f05000").unwrap();
+
+		backend.apply(vec![account(outer, 0, Some(outer_code), &[])], Vec::new(), false);
+
+		let (executed, calls) = execute_evm_with_calls(
+			true,
+			caller,
+			U256::zero(),
+			100000,
+			U256::zero(),
+			None,
+			21000,
+			|executor| ((), executor.transact_call(caller, outer, U256::zero(), Vec::new(), 100000)),
+			&Config::istanbul(),
+			&mut backend,
+		).unwrap();
+		assert!(executed.result.is_ok());
+
+		// Both frames fail before running any code and are still in the tree.
+		let calls = calls.unwrap();
+		assert_eq!(calls.calls.len(), 2);
+		assert_eq!(calls.calls[0].call_type, CallType::Call);
+		assert_eq!((calls.calls[0].from, calls.calls[0].to), (outer, inner));
+		assert_eq!(calls.calls[0].value, Some(U256::one()));
+		assert_eq!(calls.calls[0].error.as_deref(), Some("OutOfFund"));
+		assert_eq!(calls.calls[0].gas_used, U256::zero());
+		assert_eq!(calls.calls[1].call_type, CallType::Create);
+		assert_eq!(calls.calls[1].from, outer);
+		assert_eq!(calls.calls[1].error.as_deref(), Some("OutOfFund"));
+		assert!(calls.error.is_none());
+	}
+
+	#[test]
+	fn test_inner_call_logs() {
+		let vicinity = BackendVicinity::default();
+		let database = memory_database();
+		let mut backend = memory_state(&database, H256::zero(), &vicinity);
+
+		let caller = H160::from_low_u64_be(1);
+		let outer = H160::from_low_u64_be(0xa);
+		let inner = H160::from_low_u64_be(0xb);
+		// Emits an empty LOG0, then calls `inner` with all the gas left.
+		let outer_code = hex::decode("60006000a06000600060006000600073000000000000000000000000000000000000000b5af100").unwrap();
+		// Stops.
+		let inner_code = hex::decode("00").unwrap();
+
+		backend.apply(vec![
+			account(caller, 0, None, &[]),
+			account(outer, 0, Some(outer_code), &[]),
+			account(inner, 0, Some(inner_code), &[]),
+		], Vec::new(), false);
+
+		let executed = execute_evm(
+			caller,
+			U256::zero(),
+			100000,
+			U256::zero(),
+			None,
+			21000,
+			|executor| ((), executor.transact_call(caller, outer, U256::zero(), Vec::new(), 100000)),
+			&Config::istanbul(),
+			&mut backend,
+		).unwrap();
+		assert!(executed.result.is_ok());
+
+		// The log emitted before the inner call is not merged back with the inner call.
+		assert_eq!(executed.logs.len(), 1);
+		assert_eq!(executed.logs[0].address, outer);
+	}
+
+	#[test]
+	fn test_transaction_hash() {
+		let hash = |from| transaction_hash(from, U256::zero(), U256::one(), 21000, Some(H160::from_low_u64_be(0xa)), U256::zero(), &[]);
+		assert_eq!(hash(H160::from_low_u64_be(1)), hash(H160::from_low_u64_be(1)));
+		assert_ne!(hash(H160::from_low_u64_be(1)), hash(H160::from_low_u64_be(2)));
+	}
+
+	#[test]
+	fn test_nonce_replay() {
+		let vicinity = BackendVicinity::default();
+		let database = memory_database();
+		let mut backend = memory_state(&database, H256::zero(), &vicinity);
+
+		let caller = H160::from_low_u64_be(1);
+		let receiver = H160::from_low_u64_be(0xa);
+		let reverter = H160::from_low_u64_be(0xb);
+		backend.apply(vec![
+			account(caller, 1000000, None, &[]),
+			account(receiver, 0, None, &[]),
+			account(reverter, 0, Some(hex::decode("60006000fd").unwrap()), &[]),
+		], Vec::new(), false);
+
+		let config = Config::istanbul();
+		let send = |backend: &mut State, to: Option<H160>, gas_limit: u64, nonce: u64| {
+			execute_evm(
+				caller,
+				U256::zero(),
+				gas_limit,
+				U256::one(),
+				Some(U256::from(nonce)),
+				intrinsic_gas(&config, to.is_none(), &[]),
+				|executor| ((), match to {
+					Some(to) => executor.transact_call(caller, to, U256::zero(), Vec::new(), gas_limit as usize),
+					None => executor.transact_create(caller, U256::zero(), Vec::new(), gas_limit as usize),
+				}),
+				&config,
+				backend,
+			)
+		};
+
+		assert!(send(&mut backend, Some(receiver), 21000, 0).unwrap().result.is_ok());
+		assert_eq!(backend.basic(caller).nonce, U256::from(1));
+
+		// Replays, nonces ahead of the sender and gas limits below the intrinsic gas are rejected
+		// without charging a fee.
+		let balance = backend.basic(caller).balance;
+		assert!(matches!(send(&mut backend, Some(receiver), 21000, 0), Err(Error::NonceTooLow(n, m)) if n == U256::from(1) && m.is_zero()));
+		assert!(matches!(send(&mut backend, Some(receiver), 21000, 3), Err(Error::NonceTooHigh(n, m)) if n == U256::from(1) && m == U256::from(3)));
+		assert!(matches!(send(&mut backend, Some(receiver), 20000, 1), Err(Error::IntrinsicGasTooLow(21000, 20000))));
+		assert_eq!(backend.basic(caller).balance, balance);
+		assert_eq!(backend.basic(caller).nonce, U256::from(1));
+
+		// Failed transactions use their nonce once, and so does a deployment which also increments
+		// the nonce when creating the contract.
+		assert!(send(&mut backend, Some(reverter), 21002, 1).unwrap().result.is_err());
+		assert_eq!(backend.basic(caller).nonce, U256::from(2));
+		assert!(send(&mut backend, Some(reverter), 30000, 2).unwrap().result.is_err());
+		assert_eq!(backend.basic(caller).nonce, U256::from(3));
+		assert!(send(&mut backend, None, 60000, 3).unwrap().result.is_ok());
+		assert_eq!(backend.basic(caller).nonce, U256::from(4));
+		assert!(send(&mut backend, None, 60000, 3).is_err());
+	}
+
+	#[test]
+	fn test_coinbase_fee() {
+		let sender = H160::from_low_u64_be(1);
+		let receiver = H160::from_low_u64_be(0xa);
+		let coinbase = H160::from_low_u64_be(0xc);
+		let config = Config::istanbul();
+
+		// Returns the balances of the sender, the receiver and the coinbase after a transfer of 5
+		// with a gas price of 3.
+		let transfer = |base_fee: u64| -> Result<(U256, U256, U256), Error> {
+			let vicinity = BackendVicinity {
+				block_coinbase: coinbase,
+				block_base_fee: U256::from(base_fee),
+				..Default::default()
+			};
+			let database = memory_database();
+			let mut backend = memory_state(&database, H256::zero(), &vicinity);
+			backend.apply(vec![account(sender, 1000000, None, &[])], Vec::new(), false);
+
+			execute_evm(
+				sender,
+				U256::from(5),
+				50000,
+				U256::from(3),
+				None,
+				21000,
+				|executor| ((), executor.transact_call(sender, receiver, U256::from(5), Vec::new(), 50000)),
+				&config,
+				&mut backend,
+			)?.result?;
+			Ok((backend.basic(sender).balance, backend.basic(receiver).balance, backend.basic(coinbase).balance))
+		};
+
+		// The coinbase gets the fee of the used gas, not of the gas limit.
+		let (sender_balance, receiver_balance, coinbase_balance) = transfer(0).unwrap();
+		assert_eq!(coinbase_balance, U256::from(21000 * 3));
+		assert_eq!(receiver_balance, U256::from(5));
+		assert_eq!(sender_balance + receiver_balance + coinbase_balance, U256::from(1000000));
+
+		// The base fee is burnt.
+		let (sender_balance, receiver_balance, coinbase_balance) = transfer(1).unwrap();
+		assert_eq!(coinbase_balance, U256::from(21000 * 2));
+		assert_eq!(sender_balance + receiver_balance + coinbase_balance + 21000, U256::from(1000000));
+
+		assert!(matches!(transfer(4), Err(Error::GasPriceTooLow)));
+	}
+
+	#[test]
+	fn test_parse_gas() {
+		assert_eq!(parse_gas("21000"), Ok(21000));
+		assert_eq!(parse_gas("0x5208"), Ok(21000));
+		assert_eq!(parse_gas("0xffffffffffffffff"), Ok(u64::MAX));
+		assert_eq!(parse_gas("30000000000"), Ok(30_000_000_000));
+		assert!(parse_gas("18446744073709551616").is_err());
+		assert!(parse_gas("0x10000000000000000").is_err());
+		assert!(parse_gas("-1").is_err());
+		assert!(parse_gas("0x").is_err());
+	}
+
+	#[test]
+	fn test_intrinsic_gas() {
+		let data = [0u8, 1, 0, 2];
+		assert_eq!(intrinsic_gas(&Config::istanbul(), false, &[]), 21000);
+		assert_eq!(intrinsic_gas(&Config::istanbul(), false, &data), 21000 + 2 * 4 + 2 * 16);
+		assert_eq!(intrinsic_gas(&Config::istanbul(), true, &data), 53000 + 2 * 4 + 2 * 16);
+		assert_eq!(intrinsic_gas(&Config::frontier(), true, &data), 21000 + 2 * 4 + 2 * 68);
+	}
+
+	#[test]
+	fn test_estimate_gas() {
+		// Needs 30000 gas, of which 5000 are refunded.
+		let execute = |gas_limit: u64| -> Result<Executed<()>, Error> {
+			let result = match gas_limit >= 30000 {
+				true => Ok(()),
+				false => Err(Error::ExitReasonFailed(ExitError::OutOfGas)),
+			};
+			Ok(Executed { result, gas_used: U256::from(25000), intrinsic_gas: U256::from(21000), logs: Vec::new() })
+		};
+		assert_eq!(estimate_gas(1000000, execute).unwrap(), (30000, U256::from(25000)));
+		assert_eq!(estimate_gas(30000, execute).unwrap(), (30000, U256::from(25000)));
+		assert!(estimate_gas(29999, execute).is_err());
+	}
 
-	Ok(())
-}
\ No newline at end of file
+	#[test]
+	fn test_revert_reason() {
+		// Error("Not enough")
+		let error = hex::decode("08c379a0\
+			0000000000000000000000000000000000000000000000000000000000000020\
+			000000000000000000000000000000000000000000000000000000000000000a\
+			4e6f7420656e6f75676800000000000000000000000000000000000000000000").unwrap();
+		assert_eq!(revert_reason(&error), "Not enough");
+
+		// Panic(0x11)
+		let panic = hex::decode("4e487b71\
+			0000000000000000000000000000000000000000000000000000000000000011").unwrap();
+		assert_eq!(revert_reason(&panic), "Panic(0x11): arithmetic overflow or underflow");
+
+		// Custom errors and malformed data fall back to hex.
+		assert_eq!(revert_reason(&[0xde, 0xad, 0xbe, 0xef]), "0xdeadbeef");
+		assert_eq!(revert_reason(&error[..40]), format!("0x{}", hex::encode(&error[..40])));
+	}
+
+	#[test]
+	fn test_transaction_context() {
+		let caller = H160::from_low_u64_be(1);
+		let contract = H160::from_low_u64_be(0xa);
+		// Returns ORIGIN and GASPRICE.
+		let code = hex::decode("326000523a60205260406000f3").unwrap();
+		let context = |vicinity: &BackendVicinity| -> (H160, U256) {
+			let database = memory_database();
+			let mut backend = memory_state(&database, H256::zero(), vicinity);
+			backend.apply(vec![
+				account(caller, 1000000, None, &[]),
+				account(contract, 0, Some(code.clone()), &[]),
+			], Vec::new(), false);
+			let executed = execute_evm(
+				caller,
+				U256::zero(),
+				30000,
+				U256::from(3),
+				None,
+				21000,
+				|executor| {
+					let (reason, output) = executor.transact_call(caller, contract, U256::zero(), Vec::new(), 30000);
+					(output.clone(), (reason, output))
+				},
+				&Config::istanbul(),
+				&mut backend,
+			).unwrap();
+			let output = executed.result.unwrap();
+			(H160::from_slice(&output[12..32]), U256::from(&output[32..64]))
+		};
+
+		// The transaction sets ORIGIN and GASPRICE, unless the vicinity does.
+		assert_eq!(context(&BackendVicinity::default()), (caller, U256::from(3)));
+		let vicinity = BackendVicinity { origin: Some(H160::from_low_u64_be(0xb)), gas_price: Some(U256::from(7)), ..Default::default() };
+		assert_eq!(context(&vicinity), (H160::from_low_u64_be(0xb), U256::from(7)));
+	}
+}
@@ -0,0 +1,128 @@
+use std::{fmt, str};
+
+use evm::Config;
+
+/// Hard fork whose gas schedule and instruction set are used for execution.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Fork {
+	/// Frontier.
+	Frontier,
+
+	/// Homestead: `DELEGATECALL` and the higher contract creation transaction cost.
+	Homestead,
+
+	/// Byzantium, including the Tangerine Whistle and Spurious Dragon repricings:
+	/// `REVERT`, `RETURNDATASIZE`, `RETURNDATACOPY`.
+	Byzantium,
+
+	/// Constantinople as activated together with Petersburg (without EIP-1283):
+	/// bitwise shifting, `CREATE2`, `EXTCODEHASH`.
+	Constantinople,
+
+	/// Istanbul: `CHAINID`, `SELFBALANCE`, EIP-2200 storage metering and cheaper calldata.
+	Istanbul,
+}
+
+impl str::FromStr for Fork {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"frontier" => Ok(Fork::Frontier),
+			"homestead" => Ok(Fork::Homestead),
+			"byzantium" => Ok(Fork::Byzantium),
+			"constantinople" | "petersburg" => Ok(Fork::Constantinople),
+			"istanbul" => Ok(Fork::Istanbul),
+			e => Err(format!("Invalid fork: {}", e)),
+		}
+	}
+}
+
+impl Fork {
+	/// Returns static str describing the fork.
+	pub fn as_str(&self) -> &'static str {
+		match *self {
+			Fork::Frontier => "frontier",
+			Fork::Homestead => "homestead",
+			Fork::Byzantium => "byzantium",
+			Fork::Constantinople => "constantinople",
+			Fork::Istanbul => "istanbul",
+		}
+	}
+
+	/// Returns the EVM configuration of the fork.
+	pub fn config(&self) -> Config {
+		match *self {
+			Fork::Frontier => Config::frontier(),
+			Fork::Homestead => Config {
+				gas_transaction_create: 53000,
+				has_delegate_call: true,
+				..Config::frontier()
+			},
+			Fork::Byzantium => Config {
+				// EIP-150
+				gas_ext_code: 700,
+				gas_ext_code_hash: 700,
+				gas_balance: 400,
+				gas_sload: 200,
+				gas_suicide: 5000,
+				gas_suicide_new_account: 25000,
+				gas_call: 700,
+				err_on_call_with_more_gas: false,
+				call_l64_after_gas: true,
+				// EIP-160, EIP-161, EIP-170
+				gas_expbyte: 50,
+				empty_considered_exists: false,
+				create_increase_nonce: true,
+				create_contract_limit: Some(0x6000),
+				has_revert: true,
+				has_return_data: true,
+				..Fork::Homestead.config()
+			},
+			Fork::Constantinople => Config {
+				gas_ext_code_hash: 400,
+				has_create2: true,
+				has_bitwise_shifting: true,
+				has_ext_code_hash: true,
+				..Fork::Byzantium.config()
+			},
+			Fork::Istanbul => Config::istanbul(),
+		}
+	}
+
+	/// Returns all forks, oldest first.
+	pub fn all_types() -> Vec<Fork> {
+		vec![Fork::Frontier, Fork::Homestead, Fork::Byzantium, Fork::Constantinople, Fork::Istanbul]
+	}
+}
+
+impl fmt::Display for Fork {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}", self.as_str())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::Fork;
+
+	#[test]
+	fn test_fork_parsing() {
+		for fork in Fork::all_types() {
+			assert_eq!(fork, fork.as_str().parse().unwrap());
+		}
+		assert_eq!(Fork::Constantinople, "petersburg".parse().unwrap());
+		assert!("london".parse::<Fork>().is_err());
+	}
+
+	#[test]
+	fn test_fork_config() {
+		assert!(!Fork::Frontier.config().has_delegate_call);
+		assert_eq!(Fork::Homestead.config().gas_transaction_create, 53000);
+		assert!(Fork::Byzantium.config().has_revert);
+		assert!(!Fork::Byzantium.config().has_create2);
+		assert!(Fork::Constantinople.config().has_create2);
+		assert!(!Fork::Constantinople.config().sstore_gas_metering);
+		assert!(Fork::Istanbul.config().has_chain_id);
+	}
+}
@@ -0,0 +1,344 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use aes_ctr::cipher::stream::{NewStreamCipher, SyncStreamCipher};
+use aes_ctr::Aes128Ctr;
+use ethereum_types::{H160, H256, U256};
+use hmac::Hmac;
+use rand::RngCore;
+use secp256k1::{PublicKey, SecretKey};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use sha3::{Digest, Keccak256};
+use structopt::StructOpt;
+use subtle::ConstantTimeEq;
+
+use crate::executer::{self, Error};
+use crate::transaction::{public_to_address, SignedTransaction};
+
+// ./target/debug/bloom-evm account new --password-file ./password
+// ./target/debug/bloom-evm account transfer --sign-with ./test-db/keystore/9d8a...json --password-file ./password --to ... --value 1 --gas 21000 --gas-price 0
+
+/// scrypt cost of new key files, the "light" parameters of geth.
+const SCRYPT_LOG_N: u8 = 12;
+const SCRYPT_R: u32 = 8;
+const SCRYPT_P: u32 = 6;
+
+#[derive(Debug, StructOpt, Clone)]
+pub struct PasswordOpts {
+	/// The password of the key file
+	#[structopt(long = "password")]
+	pub password: Option<String>,
+
+	/// The file holding the password of the key file
+	#[structopt(long = "password-file")]
+	pub password_file: Option<String>,
+}
+
+impl PasswordOpts {
+	/// The password given by the options, without the trailing newline of a password file.
+	pub fn password(&self) -> String {
+		match (&self.password, &self.password_file) {
+			(Some(password), _) => password.clone(),
+			(None, Some(file)) => {
+				let password = fs::read_to_string(file).expect("Password file not found");
+				password.trim_end_matches(['\n', '\r']).to_string()
+			},
+			(None, None) => panic!("--password or --password-file must be provided"),
+		}
+	}
+}
+
+#[derive(Debug, StructOpt, Clone)]
+pub struct SignOpts {
+	/// Sign the transaction with the key file and send it from its account, instead of --from
+	#[structopt(long = "sign-with")]
+	pub sign_with: Option<String>,
+
+	#[structopt(flatten)]
+	pub password: PasswordOpts,
+}
+
+/// Sender of a transaction.
+pub enum Signer {
+	/// An account signing with its key.
+	Key(SecretKey, H160),
+	/// An account trusted without a signature.
+	Unsigned(H160),
+}
+
+impl SignOpts {
+	/// The signer given by `--sign-with`, or the unsigned `--from` account.
+	pub fn signer(&self, from: &Option<String>) -> Signer {
+		let from = from.as_ref().map(|from| H160::from_str(from.trim_start_matches("0x")).expect("From should be a valid address"));
+		match &self.sign_with {
+			Some(path) => {
+				let secret = KeyFile::load(Path::new(path)).decrypt(&self.password.password()).unwrap_or_else(|e| panic!("{}", e));
+				let address = public_to_address(&PublicKey::from_secret_key(&secret));
+				if let Some(from) = from {
+					if from != address {
+						panic!("--from does not match the account {:?} of the key file", address);
+					}
+				}
+				Signer::Key(secret, address)
+			},
+			None => Signer::Unsigned(from.expect("--from or --sign-with must be provided")),
+		}
+	}
+}
+
+impl Signer {
+	/// Address of the account.
+	pub fn address(&self) -> H160 {
+		match self {
+			Signer::Key(_, address) | Signer::Unsigned(address) => *address,
+		}
+	}
+
+	/// Sender and hash of a transaction. A transaction signed with a key is signed for the chain id
+	/// and its sender is recovered from the signature.
+	#[allow(clippy::too_many_arguments)]
+	pub fn transaction(&self, nonce: U256, gas_price: U256, gas_limit: u64, to: Option<H160>, value: U256, data: &[u8], chain_id: U256) -> Result<(H160, H256), Error> {
+		match self {
+			Signer::Key(secret, _) => {
+				let tx = SignedTransaction::sign(nonce, gas_price, gas_limit.into(), to, value, data.to_vec(), chain_id.as_u64(), secret);
+				Ok((tx.sender()?, tx.hash))
+			},
+			Signer::Unsigned(address) => Ok((*address, executer::transaction_hash(*address, nonce, gas_price, gas_limit, to, value, data))),
+		}
+	}
+}
+
+/// A Web3 Secret Storage v3 key file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyFile {
+	pub version: u8,
+	pub id: String,
+	#[serde(default)]
+	pub address: Option<String>,
+	#[serde(alias = "Crypto")]
+	pub crypto: Crypto,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Crypto {
+	pub cipher: String,
+	pub cipherparams: CipherParams,
+	pub ciphertext: String,
+	pub kdf: String,
+	pub kdfparams: KdfParams,
+	pub mac: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CipherParams {
+	pub iv: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum KdfParams {
+	Scrypt { dklen: usize, n: u32, r: u32, p: u32, salt: String },
+	Pbkdf2 { c: u32, dklen: usize, prf: String, salt: String },
+}
+
+impl KeyFile {
+	/// Encrypt the key with the password, deriving the encryption key with scrypt.
+	pub fn encrypt(secret: &SecretKey, password: &str) -> Self {
+		let mut rng = rand::thread_rng();
+		let mut salt = [0u8; 32];
+		let mut iv = [0u8; 16];
+		let mut id = [0u8; 16];
+		rng.fill_bytes(&mut salt);
+		rng.fill_bytes(&mut iv);
+		rng.fill_bytes(&mut id);
+
+		let kdfparams = KdfParams::Scrypt {
+			dklen: 32,
+			n: 1 << SCRYPT_LOG_N,
+			r: SCRYPT_R,
+			p: SCRYPT_P,
+			salt: hex::encode(salt),
+		};
+		let derived = derive_key(&kdfparams, password).expect("Key derivation parameters are valid");
+		let mut ciphertext = secret.serialize().to_vec();
+		Aes128Ctr::new_var(&derived[..16], &iv).expect("Key and iv are 16 bytes").apply_keystream(&mut ciphertext);
+		let mac = Keccak256::digest(&[&derived[16..32], &ciphertext[..]].concat());
+
+		// A random (version 4) UUID.
+		id[6] = (id[6] & 0x0f) | 0x40;
+		id[8] = (id[8] & 0x3f) | 0x80;
+		let id = hex::encode(id);
+
+		KeyFile {
+			version: 3,
+			id: format!("{}-{}-{}-{}-{}", &id[..8], &id[8..12], &id[12..16], &id[16..20], &id[20..]),
+			address: Some(hex::encode(public_to_address(&PublicKey::from_secret_key(secret)))),
+			crypto: Crypto {
+				cipher: "aes-128-ctr".to_string(),
+				cipherparams: CipherParams { iv: hex::encode(iv) },
+				ciphertext: hex::encode(ciphertext),
+				kdf: "scrypt".to_string(),
+				kdfparams,
+				mac: hex::encode(mac),
+			},
+		}
+	}
+
+	/// Decrypt the key with the password.
+	pub fn decrypt(&self, password: &str) -> Result<SecretKey, String> {
+		if self.version != 3 {
+			return Err(format!("Unsupported key file version {}", self.version));
+		}
+		if self.crypto.cipher != "aes-128-ctr" {
+			return Err(format!("Unsupported key file cipher {}", self.crypto.cipher));
+		}
+		let derived = derive_key(&self.crypto.kdfparams, password)?;
+		let mut ciphertext = hex::decode(&self.crypto.ciphertext).map_err(|_| "Key file ciphertext is invalid")?;
+		let iv = hex::decode(&self.crypto.cipherparams.iv).map_err(|_| "Key file iv is invalid")?;
+
+		let expected_mac = hex::decode(&self.crypto.mac).map_err(|_| "Key file mac is invalid")?;
+
+		// Compared in constant time, not to tell how much of a forged mac is right.
+		let mac = Keccak256::digest(&[&derived[16..32], &ciphertext[..]].concat());
+		if !bool::from(mac.as_slice().ct_eq(&expected_mac)) {
+			return Err("Wrong password for the key file".to_string());
+		}
+
+		Aes128Ctr::new_var(&derived[..16], &iv).map_err(|_| "Key file iv is invalid")?.apply_keystream(&mut ciphertext);
+		SecretKey::parse_slice(&ciphertext).map_err(|_| "Key file holds an invalid key".to_string())
+	}
+
+	/// Read a key file.
+	pub fn load(path: &Path) -> Self {
+		let file = File::open(path).unwrap_or_else(|_| panic!("Key file {} not found", path.display()));
+		serde_json::from_reader(file).unwrap_or_else(|e| panic!("Key file {} is invalid: {}", path.display(), e))
+	}
+
+	/// Write the key file into the keystore directory, named after its address.
+	pub fn save(&self, dir: &Path) -> PathBuf {
+		fs::create_dir_all(dir).expect("Failed to create the keystore directory");
+		let address = self.address.as_ref().expect("Key file has an address");
+		let path = dir.join(format!("{}.json", address));
+		if path.exists() {
+			panic!("Key file {} already exists", path.display());
+		}
+
+		// Only the owner can read the key file.
+		let mut options = OpenOptions::new();
+		options.write(true).create_new(true);
+		#[cfg(unix)]
+		options.mode(0o600);
+		let mut file = options.open(&path).expect("Failed to create the key file");
+		file.write_all(serde_json::to_string_pretty(self).unwrap().as_bytes()).expect("Failed to write the key file");
+		path
+	}
+}
+
+/// Derive the 32 bytes key used to encrypt and authenticate a key file.
+fn derive_key(params: &KdfParams, password: &str) -> Result<Vec<u8>, String> {
+	match params {
+		KdfParams::Scrypt { dklen, n, r, p, salt } => {
+			let salt = hex::decode(salt).map_err(|_| "Key file salt is invalid")?;
+			if *dklen < 32 || !n.is_power_of_two() {
+				return Err("Key file scrypt parameters are invalid".to_string());
+			}
+			let params = scrypt::ScryptParams::new(n.trailing_zeros() as u8, *r, *p).map_err(|_| "Key file scrypt parameters are invalid")?;
+			let mut derived = vec![0u8; *dklen];
+			scrypt::scrypt(password.as_bytes(), &salt, &params, &mut derived).map_err(|_| "Key file scrypt parameters are invalid")?;
+			Ok(derived)
+		},
+		KdfParams::Pbkdf2 { c, dklen, prf, salt } => {
+			let salt = hex::decode(salt).map_err(|_| "Key file salt is invalid")?;
+			if prf != "hmac-sha256" || *dklen < 32 {
+				return Err("Key file pbkdf2 parameters are unsupported".to_string());
+			}
+			let mut derived = vec![0u8; *dklen];
+			pbkdf2::pbkdf2::<Hmac<Sha256>>(password.as_bytes(), &salt, *c, &mut derived);
+			Ok(derived)
+		},
+	}
+}
+
+/// Directory of the key files of a data directory.
+pub fn keystore_dir(data_dir: &str) -> PathBuf {
+	Path::new(data_dir).join("keystore")
+}
+
+/// The key files of the keystore directory with the address of their account, sorted by path.
+pub fn list(dir: &Path) -> Vec<(PathBuf, Option<String>)> {
+	let mut keys: Vec<_> = match fs::read_dir(dir) {
+		Ok(entries) => entries
+			.filter_map(|entry| entry.ok().map(|e| e.path()))
+			.filter(|path| path.is_file())
+			.map(|path| {
+				let address = File::open(&path).ok()
+					.and_then(|f| serde_json::from_reader::<_, KeyFile>(f).ok())
+					.and_then(|key| key.address);
+				(path, address)
+			})
+			.collect(),
+		Err(_) => Vec::new(),
+	};
+	keys.sort();
+	keys
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{KeyFile, KdfParams};
+	use secp256k1::SecretKey;
+
+	#[test]
+	fn test_key_file() {
+		let secret = SecretKey::parse(&[0x46; 32]).unwrap();
+		let mut key = KeyFile::encrypt(&secret, "foo");
+		assert_eq!(key.address.as_deref(), Some("9d8a62f656a8d1615c1294fd71e9cfb3e4855a4f"));
+		assert_eq!(key.decrypt("foo").unwrap(), secret);
+		assert!(key.decrypt("bar").is_err());
+
+		let json = serde_json::to_string(&key).unwrap();
+		key = serde_json::from_str(&json).unwrap();
+		assert!(matches!(key.crypto.kdfparams, KdfParams::Scrypt { .. }));
+		assert_eq!(key.decrypt("foo").unwrap(), secret);
+	}
+
+	#[cfg(unix)]
+	#[test]
+	fn test_save_key_file() {
+		use std::os::unix::fs::PermissionsExt;
+
+		let dir = std::env::temp_dir().join(format!("bloom-evm-keystore-{}", std::process::id()));
+		let key = KeyFile::encrypt(&SecretKey::parse(&[0x46; 32]).unwrap(), "foo");
+		let path = key.save(&dir);
+		let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+		let loaded = KeyFile::load(&path);
+		std::fs::remove_dir_all(&dir).unwrap();
+
+		assert_eq!(mode & 0o777, 0o600);
+		assert_eq!(loaded.crypto.mac, key.crypto.mac);
+	}
+
+	#[test]
+	fn test_pbkdf2_key_file() {
+		// The PBKDF2 test vector of the Web3 Secret Storage Definition.
+		let key: KeyFile = serde_json::from_str(r#"{
+			"crypto": {
+				"cipher": "aes-128-ctr",
+				"cipherparams": { "iv": "6087dab2f9fdbbfaddc31a909735c1e6" },
+				"ciphertext": "5318b4d5bcd28de64ee5559e671353e16f075ecae9f99c7a79a38af5f869aa46",
+				"kdf": "pbkdf2",
+				"kdfparams": { "c": 262144, "dklen": 32, "prf": "hmac-sha256", "salt": "ae3cd4e7013836a3df6bd7241b12db061dbe2c6785853cce422d148a624ce0bd" },
+				"mac": "517ead924a9d0dc3124507e3393d175ce3ff7c1e96529c6c555ce9e51205e9b2"
+			},
+			"id": "3198bc9c-6672-5ab3-d995-4942343ae5b6",
+			"version": 3
+		}"#).unwrap();
+		let secret = key.decrypt("testpassword").unwrap();
+		assert_eq!(hex::encode(secret.serialize()), "7a28b5ba57c53603b0b07b56bba752f7784bf506fa95edc395f5cf6c7514fe9d");
+	}
+}
@@ -1,11 +1,45 @@
+mod abi;
+mod artifact;
 mod executer;
 mod commands;
+mod fork;
+mod keystore;
+mod vicinity;
+mod tracer;
+mod transaction;
+#[cfg(test)]
+mod test_helpers;
 
 use commands::Subcommand;
+use std::process;
 use structopt::StructOpt;
+use fork::Fork;
+use vicinity::VicinityOpts;
 
 #[derive(Debug, StructOpt)]
 pub struct Cli {
+	/// The directory of the RocksDB database
+	#[structopt(long = "data-dir", default_value = "test-db")]
+	pub data_dir: String,
+
+	/// The state pruning algorithm: archive, fast (overlayrecent), light (earlymerge) or basic (refcounted).
+	/// Recorded on first open, defaults to archive
+	#[structopt(long = "pruning")]
+	pub pruning: Option<journaldb::Algorithm>,
+
+	/// The hard fork rules used for execution: frontier, homestead, byzantium, constantinople or istanbul.
+	/// Becomes the default of the data directory once a command changes its state with it, istanbul at first
+	#[structopt(long = "fork")]
+	pub fork: Option<Fork>,
+
+	/// Inspect the state at an earlier commit, given its number or its state root. Read-only, for
+	/// `account query`, `account list` and `contract call`
+	#[structopt(long = "at")]
+	pub at: Option<String>,
+
+	#[structopt(flatten)]
+	pub vicinity: VicinityOpts,
+
 	#[structopt(subcommand)]
 	pub subcmd: Option<Subcommand>,
 }
@@ -16,9 +50,11 @@ fn main() {
 	//println!("{:#?}", cli);
 
 	if let Some(ref subcmd) = cli.subcmd {
-		subcmd.run();
+		if let Err(e) = subcmd.run(&cli) {
+			eprintln!("{}", e);
+			process::exit(e.exit_code());
+		}
 	} else {
 		println!("{:#?}", cli);
 	}
 }
-
@@ -0,0 +1,78 @@
+//! Fixtures shared by the tests.
+
+use std::collections::BTreeMap;
+use std::ops::Deref;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::{env, fs, process};
+
+use bloom_state::{self as state, AccountFactory, BackendVicinity, Factories, State};
+use ethereum_types::{H160, H256, U256};
+use evm::backend::{Apply, Basic};
+use kvdb_memorydb::InMemory;
+use kvdb_rocksdb::{Database, DatabaseConfig};
+use trie_db::TrieSpec;
+
+/// A RocksDB database in a directory of its own, removed on drop.
+pub struct TempDatabase {
+	database: Arc<Database>,
+	path: PathBuf,
+}
+
+impl TempDatabase {
+	/// Open an empty database, `name` telling apart the databases of the tests run in parallel.
+	pub fn new(name: &str) -> Self {
+		let path = env::temp_dir().join(format!("bloom-evm-{}-{}", name, process::id()));
+		let _ = fs::remove_dir_all(&path);
+		let config = DatabaseConfig::with_columns(state::COLUMN_COUNT);
+		let database = Arc::new(Database::open(&config, path.to_str().unwrap()).unwrap());
+		TempDatabase { database, path }
+	}
+}
+
+impl Deref for TempDatabase {
+	type Target = Arc<Database>;
+
+	fn deref(&self) -> &Arc<Database> {
+		&self.database
+	}
+}
+
+impl Drop for TempDatabase {
+	fn drop(&mut self) {
+		let _ = fs::remove_dir_all(&self.path);
+	}
+}
+
+/// An in-memory database with the columns of a data directory, for the tests of the execution.
+pub fn memory_database() -> Arc<InMemory> {
+	Arc::new(kvdb_memorydb::create(state::COLUMN_COUNT))
+}
+
+/// Open the state at `root` of an in-memory database, empty for `H256::zero()`.
+///
+/// The account tries are keyed by address, like in a data directory, so that `list_address`
+/// lists the accounts.
+pub fn memory_state<'a>(database: &Arc<InMemory>, root: H256, vicinity: &'a BackendVicinity) -> State<'a> {
+	let db = journaldb::new(database.clone(), journaldb::Algorithm::Archive, state::COL_STATE);
+	let factories = Factories {
+		trie: ethtrie::TrieFactory::new(TrieSpec::Generic, ethtrie::Layout),
+		accountdb: AccountFactory::default(),
+	};
+	match root == H256::zero() {
+		true => State::new(vicinity, db, factories),
+		false => State::from_existing(root, vicinity, db, factories).expect("State root is missing"),
+	}
+}
+
+/// Change creating or replacing the account at `address`, with a zero nonce and the storage
+/// slots given as `(key, value)` pairs of low u64s.
+pub fn account(address: H160, balance: u64, code: Option<Vec<u8>>, storage: &[(u64, u64)]) -> Apply<BTreeMap<H256, H256>> {
+	Apply::Modify {
+		address,
+		basic: Basic { balance: U256::from(balance), nonce: U256::zero() },
+		code,
+		storage: storage.iter().map(|&(k, v)| (H256::from_low_u64_be(k), H256::from_low_u64_be(v))).collect(),
+		reset_storage: false,
+	}
+}
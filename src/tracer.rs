@@ -0,0 +1,447 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io;
+use std::rc::Rc;
+use std::str::FromStr;
+
+use ethereum_types::{H160, H256, U256};
+use evm::executor::{CallType, Frame, Step, Tracer};
+use evm::{ExitReason, Stack};
+use serde::{Serialize, Serializer};
+use serde_json::json;
+use structopt::StructOpt;
+
+use crate::executer::Error;
+
+#[derive(Debug, StructOpt, Clone)]
+pub struct TraceOpts {
+	/// Trace the execution, `opcodes` (default) for the executed opcodes or `calls` for the tree of message calls
+	#[structopt(long = "trace")]
+	pub trace: Option<Option<TraceKind>>,
+
+	/// Write the trace to the file instead of stdout
+	#[structopt(long = "trace-file")]
+	pub trace_file: Option<String>,
+}
+
+/// Kind of execution trace.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TraceKind {
+	/// Every executed opcode.
+	Opcodes,
+	/// Tree of the message calls.
+	Calls,
+}
+
+impl FromStr for TraceKind {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"opcodes" => Ok(TraceKind::Opcodes),
+			"calls" => Ok(TraceKind::Calls),
+			e => Err(format!("Invalid trace: {}", e)),
+		}
+	}
+}
+
+impl TraceOpts {
+	/// Whether the tree of message calls is requested, which `execute_evm_with_calls` records for
+	/// a transaction.
+	pub fn calls(&self) -> bool {
+		self.trace == Some(Some(TraceKind::Calls))
+	}
+
+	/// Create the opcode tracer, if requested, for a transaction executed by
+	/// `execute_evm_with_calls`.
+	pub fn struct_logger(&self) -> Option<Rc<RefCell<StructLogger>>> {
+		match self.trace {
+			Some(None) | Some(Some(TraceKind::Opcodes)) => Some(Rc::new(RefCell::new(StructLogger::default()))),
+			_ => None,
+		}
+	}
+
+	/// Write the trace of a transaction, from its opcode tracer or its tree of message calls,
+	/// given the gas it used and its output.
+	pub fn write_transaction(
+		&self,
+		logger: Option<Rc<RefCell<StructLogger>>>,
+		calls: Option<CallFrame>,
+		gas_used: U256,
+		result: Result<&[u8], &Error>,
+	) {
+		match logger {
+			Some(logger) => self.write(&logger.take().into_trace(gas_used, result)),
+			None if self.calls() => self.write(&json!(calls)),
+			None => (),
+		}
+	}
+
+	/// Write the trace to stdout or the trace file.
+	pub fn write(&self, trace: &serde_json::Value) {
+		match &self.trace_file {
+			Some(file) => {
+				let f = File::create(file).expect("trace file can't be created");
+				serde_json::to_writer_pretty(f, trace).expect("something went wrong writing the trace");
+			},
+			None => {
+				serde_json::to_writer_pretty(io::stdout(), trace).expect("something went wrong writing the trace");
+				println!();
+			},
+		}
+	}
+}
+
+/// Executed opcode, in the shape of the geth `structLogs` entries.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StructLog {
+	pub pc: usize,
+	pub op: String,
+	pub gas: usize,
+	pub gas_cost: usize,
+	pub depth: usize,
+	pub stack: Vec<String>,
+	pub memory: Vec<String>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub storage: Option<BTreeMap<String, String>>,
+}
+
+/// Tracer recording every executed opcode.
+#[derive(Debug, Default)]
+pub struct StructLogger {
+	pub logs: Vec<StructLog>,
+	/// Steps waiting for their result, with the slot read by `SLOAD` steps.
+	pending: Vec<(usize, Option<(H160, H256)>)>,
+	/// Storage slots read or written so far, by account.
+	storage: HashMap<H160, BTreeMap<H256, H256>>,
+}
+
+impl StructLogger {
+	/// Trace in the shape of the geth `debug_traceTransaction` result.
+	pub fn into_trace(self, gas_used: U256, result: Result<&[u8], &Error>) -> serde_json::Value {
+		let return_value = match result {
+			Ok(output) => output,
+			Err(Error::ExitReasonRevert(_, data)) => data.as_slice(),
+			Err(_) => &[],
+		};
+		json!({
+			"gas": gas_used.as_u64(),
+			"failed": result.is_err(),
+			"returnValue": hex::encode(return_value),
+			"structLogs": self.logs,
+		})
+	}
+
+	fn storage_of(&self, address: &H160) -> BTreeMap<String, String> {
+		self.storage.get(address).map(|storage| {
+			storage.iter().map(|(k, v)| (hex::encode(k), hex::encode(v))).collect()
+		}).unwrap_or_default()
+	}
+}
+
+impl Tracer for StructLogger {
+	fn step(&mut self, step: &Step) {
+		let mut stack = stack_words(step.stack);
+		let memory = step.memory.get(0, step.memory.len());
+
+		let mut storage = None;
+		let mut sload = None;
+		match step.opcode {
+			// SLOAD, the value is known once executed
+			0x54 => sload = step.stack.peek(0).ok().map(|key| (step.address, key)),
+			// SSTORE
+			0x55 => if let (Ok(key), Ok(value)) = (step.stack.peek(0), step.stack.peek(1)) {
+				self.storage.entry(step.address).or_default().insert(key, value);
+				storage = Some(self.storage_of(&step.address));
+			},
+			_ => (),
+		}
+
+		stack.reverse();
+		self.pending.push((self.logs.len(), sload));
+		self.logs.push(StructLog {
+			pc: step.pc,
+			op: opcode_name(step.opcode),
+			gas: step.gas,
+			gas_cost: 0,
+			depth: step.depth,
+			stack: stack.iter().map(|word| format!("{:#x}", U256::from_big_endian(word.as_bytes()))).collect(),
+			memory: memory.chunks(32).map(hex::encode).collect(),
+			storage,
+		});
+	}
+
+	fn step_result(&mut self, gas_cost: usize, stack: &Stack) {
+		if let Some((index, sload)) = self.pending.pop() {
+			self.logs[index].gas_cost = gas_cost;
+			if let (Some((address, key)), Ok(value)) = (sload, stack.peek(0)) {
+				self.storage.entry(address).or_default().insert(key, value);
+				self.logs[index].storage = Some(self.storage_of(&address));
+			}
+		}
+	}
+}
+
+/// Message call, in the shape of the geth `callTracer` frames.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CallFrame {
+	#[serde(rename = "type", serialize_with = "serialize_call_type")]
+	pub call_type: CallType,
+	pub from: H160,
+	pub to: H160,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub value: Option<U256>,
+	pub gas: U256,
+	pub gas_used: U256,
+	#[serde(serialize_with = "serialize_bytes")]
+	pub input: Vec<u8>,
+	#[serde(serialize_with = "serialize_bytes")]
+	pub output: Vec<u8>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub error: Option<String>,
+	#[serde(skip_serializing_if = "Vec::is_empty")]
+	pub calls: Vec<CallFrame>,
+}
+
+/// Tracer recording the tree of message calls.
+#[derive(Debug, Default)]
+pub struct CallTracer {
+	/// Frames entered and not exited yet, the outermost first.
+	open: Vec<CallFrame>,
+	root: Option<CallFrame>,
+}
+
+impl CallTracer {
+	/// Frame of the transaction, with the frames it called.
+	pub fn into_root(self) -> Option<CallFrame> {
+		self.root
+	}
+}
+
+impl Tracer for CallTracer {
+	fn enter(&mut self, frame: &Frame) {
+		self.open.push(CallFrame {
+			call_type: frame.call_type,
+			from: frame.from,
+			to: frame.to,
+			value: match frame.call_type {
+				CallType::DelegateCall | CallType::StaticCall => None,
+				_ => Some(frame.value),
+			},
+			gas: U256::from(frame.gas),
+			gas_used: U256::zero(),
+			input: frame.input.to_vec(),
+			output: Vec::new(),
+			error: None,
+			calls: Vec::new(),
+		});
+	}
+
+	fn exit(&mut self, reason: &ExitReason, gas_used: usize, output: &[u8]) {
+		if let Some(mut frame) = self.open.pop() {
+			frame.gas_used = U256::from(gas_used);
+			frame.output = output.to_vec();
+			frame.error = match reason {
+				ExitReason::Succeed(_) => None,
+				ExitReason::Revert(_) => Some("execution reverted".to_string()),
+				ExitReason::Error(e) => Some(format!("{:?}", e)),
+				ExitReason::Fatal(e) => Some(format!("{:?}", e)),
+			};
+			match self.open.last_mut() {
+				Some(parent) => parent.calls.push(frame),
+				None => self.root = Some(frame),
+			}
+		}
+	}
+}
+
+fn serialize_call_type<S: Serializer>(call_type: &CallType, serializer: S) -> Result<S::Ok, S::Error> {
+	serializer.serialize_str(match call_type {
+		CallType::Call => "CALL",
+		CallType::CallCode => "CALLCODE",
+		CallType::DelegateCall => "DELEGATECALL",
+		CallType::StaticCall => "STATICCALL",
+		CallType::Create => "CREATE",
+		CallType::Create2 => "CREATE2",
+	})
+}
+
+fn serialize_bytes<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+	serializer.serialize_str(&format!("0x{}", hex::encode(bytes)))
+}
+
+/// Words of the stack, from the top.
+fn stack_words(stack: &Stack) -> Vec<H256> {
+	let mut words = Vec::new();
+	while let Ok(word) = stack.peek(words.len()) {
+		words.push(word);
+	}
+	words
+}
+
+/// Mnemonic of an opcode, as printed by geth.
+pub fn opcode_name(opcode: u8) -> String {
+	let name = match opcode {
+		0x00 => "STOP",
+		0x01 => "ADD",
+		0x02 => "MUL",
+		0x03 => "SUB",
+		0x04 => "DIV",
+		0x05 => "SDIV",
+		0x06 => "MOD",
+		0x07 => "SMOD",
+		0x08 => "ADDMOD",
+		0x09 => "MULMOD",
+		0x0a => "EXP",
+		0x0b => "SIGNEXTEND",
+		0x10 => "LT",
+		0x11 => "GT",
+		0x12 => "SLT",
+		0x13 => "SGT",
+		0x14 => "EQ",
+		0x15 => "ISZERO",
+		0x16 => "AND",
+		0x17 => "OR",
+		0x18 => "XOR",
+		0x19 => "NOT",
+		0x1a => "BYTE",
+		0x1b => "SHL",
+		0x1c => "SHR",
+		0x1d => "SAR",
+		0x20 => "SHA3",
+		0x30 => "ADDRESS",
+		0x31 => "BALANCE",
+		0x32 => "ORIGIN",
+		0x33 => "CALLER",
+		0x34 => "CALLVALUE",
+		0x35 => "CALLDATALOAD",
+		0x36 => "CALLDATASIZE",
+		0x37 => "CALLDATACOPY",
+		0x38 => "CODESIZE",
+		0x39 => "CODECOPY",
+		0x3a => "GASPRICE",
+		0x3b => "EXTCODESIZE",
+		0x3c => "EXTCODECOPY",
+		0x3d => "RETURNDATASIZE",
+		0x3e => "RETURNDATACOPY",
+		0x3f => "EXTCODEHASH",
+		0x40 => "BLOCKHASH",
+		0x41 => "COINBASE",
+		0x42 => "TIMESTAMP",
+		0x43 => "NUMBER",
+		0x44 => "DIFFICULTY",
+		0x45 => "GASLIMIT",
+		0x46 => "CHAINID",
+		0x47 => "SELFBALANCE",
+		0x50 => "POP",
+		0x51 => "MLOAD",
+		0x52 => "MSTORE",
+		0x53 => "MSTORE8",
+		0x54 => "SLOAD",
+		0x55 => "SSTORE",
+		0x56 => "JUMP",
+		0x57 => "JUMPI",
+		0x58 => "PC",
+		0x59 => "MSIZE",
+		0x5a => "GAS",
+		0x5b => "JUMPDEST",
+		0x60..=0x7f => return format!("PUSH{}", opcode - 0x5f),
+		0x80..=0x8f => return format!("DUP{}", opcode - 0x7f),
+		0x90..=0x9f => return format!("SWAP{}", opcode - 0x8f),
+		0xa0..=0xa4 => return format!("LOG{}", opcode - 0xa0),
+		0xf0 => "CREATE",
+		0xf1 => "CALL",
+		0xf2 => "CALLCODE",
+		0xf3 => "RETURN",
+		0xf4 => "DELEGATECALL",
+		0xf5 => "CREATE2",
+		0xfa => "STATICCALL",
+		0xfd => "REVERT",
+		0xfe => "INVALID",
+		0xff => "SELFDESTRUCT",
+		_ => return format!("opcode 0x{:x} not defined", opcode),
+	};
+	name.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{opcode_name, StructLogger};
+	use std::cell::RefCell;
+	use std::rc::Rc;
+	use ethereum_types::{H160, H256, U256};
+	use evm::backend::ApplyBackend;
+	use evm::Config;
+	use bloom_state::BackendVicinity;
+	use crate::executer::execute_evm;
+	use crate::test_helpers::{account, memory_database, memory_state};
+
+	#[test]
+	fn test_struct_logs() {
+		let vicinity = BackendVicinity::default();
+		let database = memory_database();
+		let mut backend = memory_state(&database, H256::zero(), &vicinity);
+
+		let caller = H160::from_low_u64_be(1);
+		let contract = H160::from_low_u64_be(0xa);
+		// MSTORE 0x2a at 0, SSTORE 1 at slot 0 then SLOAD slot 0.
+		let code = hex::decode("602a60005260016000556000545000").unwrap();
+		backend.apply(vec![account(contract, 0, Some(code), &[])], Vec::new(), false);
+
+		let logger = Rc::new(RefCell::new(StructLogger::default()));
+		let executed = execute_evm(
+			caller,
+			U256::zero(),
+			100000,
+			U256::zero(),
+			None,
+			21000,
+			|executor| {
+				executor.set_tracer(logger.clone());
+				((), executor.transact_call(caller, contract, U256::zero(), Vec::new(), 79000))
+			},
+			&Config::istanbul(),
+			&mut backend,
+		).unwrap();
+		let trace = logger.take().into_trace(executed.gas_used, Ok(&[]));
+		let logs = trace["structLogs"].as_array().unwrap();
+
+		let ops: Vec<_> = logs.iter().map(|log| log["op"].as_str().unwrap()).collect();
+		assert_eq!(ops, ["PUSH1", "PUSH1", "MSTORE", "PUSH1", "PUSH1", "SSTORE", "PUSH1", "SLOAD", "POP", "STOP"]);
+		let pcs: Vec<_> = logs.iter().map(|log| log["pc"].as_u64().unwrap()).collect();
+		assert_eq!(pcs, [0, 2, 4, 5, 7, 9, 10, 12, 13, 14]);
+		assert_eq!(logs[0]["gas"], 79000);
+		assert_eq!(logs[0]["gasCost"], 3);
+		assert_eq!(logs[1]["gas"], 79000 - 3);
+		assert_eq!(logs[0]["depth"], 1);
+
+		// The stack is listed from the bottom, as quantities.
+		assert_eq!(logs[2]["stack"], serde_json::json!(["0x2a", "0x0"]));
+		assert_eq!(logs[2]["memory"], serde_json::json!([]));
+		assert_eq!(logs[3]["memory"], serde_json::json!([format!("{:064x}", 0x2a)]));
+
+		// Storage is shown from the SSTORE or the SLOAD which touches it.
+		let slot = serde_json::json!({ format!("{:064x}", 0): format!("{:064x}", 1) });
+		assert!(logs[4].get("storage").is_none());
+		assert_eq!(logs[5]["gasCost"], 20000);
+		assert_eq!(logs[5]["storage"], slot);
+		assert_eq!(logs[7]["gasCost"], 800);
+		assert_eq!(logs[7]["storage"], slot);
+		assert_eq!(logs[8]["stack"], serde_json::json!(["0x1"]));
+	}
+
+	#[test]
+	fn test_opcode_name() {
+		assert_eq!(opcode_name(0x00), "STOP");
+		assert_eq!(opcode_name(0x60), "PUSH1");
+		assert_eq!(opcode_name(0x7f), "PUSH32");
+		assert_eq!(opcode_name(0x8f), "DUP16");
+		assert_eq!(opcode_name(0x90), "SWAP1");
+		assert_eq!(opcode_name(0xa4), "LOG4");
+		assert_eq!(opcode_name(0xfa), "STATICCALL");
+		assert_eq!(opcode_name(0x0c), "opcode 0xc not defined");
+	}
+}
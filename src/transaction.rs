@@ -0,0 +1,206 @@
+use ethereum_types::{H160, H256, U256};
+use rlp::{Decodable, DecoderError, Encodable, Rlp, RlpStream};
+use sha3::{Digest, Keccak256};
+
+use crate::executer::Error;
+
+/// Half of the order of the secp256k1 curve, the highest `s` of a signature since homestead.
+const SECP256K1N_HALF: [u8; 32] = [
+	0x7f, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+	0x5d, 0x57, 0x6e, 0x73, 0x57, 0xa4, 0x50, 0x1d, 0xdf, 0xe9, 0x2f, 0x46, 0x68, 0x1b, 0x20, 0xa0,
+];
+
+/// Highest chain id whose EIP-155 `v` fits a `u64`.
+pub const MAX_CHAIN_ID: u64 = (u64::MAX - 36) / 2;
+
+/// A signed legacy transaction, with or without EIP-155 replay protection.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SignedTransaction {
+	pub nonce: U256,
+	pub gas_price: U256,
+	pub gas: U256,
+	/// Receiver, `None` for a contract creation.
+	pub to: Option<H160>,
+	pub value: U256,
+	pub data: Vec<u8>,
+	pub v: u64,
+	pub r: U256,
+	pub s: U256,
+	/// Hash of the RLP encoded transaction.
+	pub hash: H256,
+}
+
+impl Decodable for SignedTransaction {
+	fn decode(rlp: &Rlp) -> Result<Self, DecoderError> {
+		if rlp.item_count()? != 9 {
+			return Err(DecoderError::RlpIncorrectListLen);
+		}
+
+		let to = match rlp.at(3)?.is_empty() {
+			true => None,
+			false => Some(rlp.val_at(3)?),
+		};
+
+		Ok(SignedTransaction {
+			nonce: rlp.val_at(0)?,
+			gas_price: rlp.val_at(1)?,
+			gas: rlp.val_at(2)?,
+			to,
+			value: rlp.val_at(4)?,
+			data: rlp.val_at(5)?,
+			v: rlp.val_at(6)?,
+			r: rlp.val_at(7)?,
+			s: rlp.val_at(8)?,
+			hash: H256::from_slice(Keccak256::digest(rlp.as_raw()).as_slice()),
+		})
+	}
+}
+
+impl Encodable for SignedTransaction {
+	fn rlp_append(&self, stream: &mut RlpStream) {
+		stream.begin_list(9);
+		self.append_unsigned(stream);
+		stream.append(&self.v);
+		stream.append(&self.r);
+		stream.append(&self.s);
+	}
+}
+
+impl SignedTransaction {
+	/// Sign a transaction for the EIP-155 chain id, at most `MAX_CHAIN_ID`, with the key.
+	#[allow(clippy::too_many_arguments)]
+	pub fn sign(nonce: U256, gas_price: U256, gas: U256, to: Option<H160>, value: U256, data: Vec<u8>, chain_id: u64, secret: &secp256k1::SecretKey) -> Self {
+		let mut tx = SignedTransaction {
+			nonce,
+			gas_price,
+			gas,
+			to,
+			value,
+			data,
+			v: chain_id * 2 + 35,
+			r: U256::zero(),
+			s: U256::zero(),
+			hash: H256::zero(),
+		};
+		let message = secp256k1::Message::parse(tx.signing_hash().as_fixed_bytes());
+		let (signature, recovery_id) = secp256k1::sign(&message, secret);
+		let signature = signature.serialize();
+
+		tx.v += recovery_id.serialize() as u64;
+		tx.r = U256::from(&signature[..32]);
+		tx.s = U256::from(&signature[32..]);
+		tx.hash = H256::from_slice(Keccak256::digest(&rlp::encode(&tx)).as_slice());
+		tx
+	}
+
+	/// Decode a raw transaction, as hex with an optional 0x prefix.
+	pub fn from_hex(raw: &str) -> Result<Self, String> {
+		let raw = hex::decode(raw.trim().trim_start_matches("0x")).map_err(|_| "Raw transaction is not valid hex".to_string())?;
+		rlp::decode(&raw).map_err(|e| format!("Raw transaction is invalid: {}", e))
+	}
+
+	/// The EIP-155 chain id the transaction is signed for, `None` for an unprotected transaction.
+	pub fn chain_id(&self) -> Option<u64> {
+		match self.v {
+			v if v >= 35 => Some((v - 35) / 2),
+			_ => None,
+		}
+	}
+
+	/// Hash signed by the sender, which includes the chain id since EIP-155.
+	pub fn signing_hash(&self) -> H256 {
+		let mut stream = RlpStream::new();
+		stream.begin_list(if self.chain_id().is_some() { 9 } else { 6 });
+		self.append_unsigned(&mut stream);
+		if let Some(chain_id) = self.chain_id() {
+			stream.append(&chain_id);
+			stream.append(&0u8);
+			stream.append(&0u8);
+		}
+		H256::from_slice(Keccak256::digest(&stream.out()).as_slice())
+	}
+
+	fn append_unsigned(&self, stream: &mut RlpStream) {
+		stream.append(&self.nonce);
+		stream.append(&self.gas_price);
+		stream.append(&self.gas);
+		match self.to {
+			Some(ref to) => { stream.append(to); },
+			None => { stream.append_empty_data(); },
+		}
+		stream.append(&self.value);
+		stream.append(&self.data);
+	}
+
+	/// Recover the address of the sender from the signature.
+	pub fn sender(&self) -> Result<H160, Error> {
+		let recovery_id = match (self.v, self.chain_id()) {
+			(27, None) | (28, None) => self.v - 27,
+			(v, Some(chain_id)) => v - 35 - chain_id * 2,
+			_ => return Err(Error::InvalidSignature),
+		};
+		if self.r.is_zero() || self.s.is_zero() || self.s > U256::from(&SECP256K1N_HALF[..]) {
+			return Err(Error::InvalidSignature);
+		}
+
+		let mut signature = [0u8; 64];
+		self.r.to_big_endian(&mut signature[..32]);
+		self.s.to_big_endian(&mut signature[32..]);
+		let signature = secp256k1::Signature::parse(&signature);
+		let recovery_id = secp256k1::RecoveryId::parse(recovery_id as u8).map_err(|_| Error::InvalidSignature)?;
+		let message = secp256k1::Message::parse(self.signing_hash().as_fixed_bytes());
+		let public = secp256k1::recover(&message, &signature, &recovery_id).map_err(|_| Error::InvalidSignature)?;
+
+		Ok(public_to_address(&public))
+	}
+}
+
+/// Address of the account of a public key, the last 20 bytes of the hash of the key.
+pub fn public_to_address(public: &secp256k1::PublicKey) -> H160 {
+	let hash = Keccak256::digest(&public.serialize()[1..]);
+	H160::from_slice(&hash[12..])
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{SignedTransaction, MAX_CHAIN_ID};
+	use ethereum_types::{H160, H256, U256};
+	use std::str::FromStr;
+
+	// The example of EIP-155, signed with the key 0x4646...46.
+	const RAW: &str = "0xf86c098504a817c800825208943535353535353535353535353535353535353535880de0b6b3a76400008025a028ef61340bd939bc2195fe537567866003e1a15d3c71ff63e1590620aa636276a067cbe9d8997f761aecb703304b3800ccf555c9f3dc64214b297fb1966a3b6d83";
+
+	#[test]
+	fn test_eip155_transaction() {
+		let tx = SignedTransaction::from_hex(RAW).unwrap();
+		assert_eq!(tx.nonce, U256::from(9));
+		assert_eq!(tx.gas, U256::from(21000));
+		assert_eq!(tx.to, Some(H160::from_str("3535353535353535353535353535353535353535").unwrap()));
+		assert_eq!(tx.value, U256::from_dec_str("1000000000000000000").unwrap());
+		assert_eq!(tx.chain_id(), Some(1));
+		assert_eq!(tx.signing_hash(), H256::from_str("daf5a779ae972f972197303d7b574746c7ef83eadac0f2791ad23db92e4c8e53").unwrap());
+		assert_eq!(tx.sender().unwrap(), H160::from_str("9d8a62f656a8d1615c1294fd71e9cfb3e4855a4f").unwrap());
+
+		let mut tampered = tx.clone();
+		tampered.value = U256::from(2);
+		assert_ne!(tampered.sender().unwrap(), tx.sender().unwrap());
+		tampered.s = U256::max_value();
+		assert!(tampered.sender().is_err());
+
+		assert!(SignedTransaction::from_hex("0xc0").is_err());
+	}
+
+	#[test]
+	fn test_sign_transaction() {
+		let tx = SignedTransaction::from_hex(RAW).unwrap();
+		let secret = secp256k1::SecretKey::parse(&[0x46; 32]).unwrap();
+		let signed = SignedTransaction::sign(tx.nonce, tx.gas_price, tx.gas, tx.to, tx.value, tx.data.clone(), 1, &secret);
+		assert_eq!(signed, tx);
+		assert_eq!(format!("0x{}", hex::encode(rlp::encode(&signed))), RAW);
+
+		// The highest chain id still fits the `v` of the signature.
+		let signed = SignedTransaction::sign(tx.nonce, tx.gas_price, tx.gas, tx.to, tx.value, tx.data.clone(), MAX_CHAIN_ID, &secret);
+		assert_eq!(signed.chain_id(), Some(MAX_CHAIN_ID));
+		assert_eq!(signed.sender().unwrap(), tx.sender().unwrap());
+	}
+}
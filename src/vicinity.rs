@@ -0,0 +1,139 @@
+use std::fs::File;
+use std::str::FromStr; // !!! Necessary for H160::from_str(address).expect("...");
+
+use bloom_state::BackendVicinity;
+use bloom_state::units::parse_amount;
+use ethereum_types::{H160, U256};
+use structopt::StructOpt;
+
+// ./target/debug/bloom-evm --block-number 10 --timestamp 1600000000 --chain-id 1 contract call ...
+// ./target/debug/bloom-evm --env ./env.json contract call ...
+
+// Block context seen by the executed contracts.
+#[derive(Debug, Clone, StructOpt)]
+pub struct VicinityOpts {
+	/// JSON file with the block context, the flags below override its fields
+	#[structopt(long = "env")]
+	pub env: Option<String>,
+
+	/// The block number (NUMBER)
+	#[structopt(long = "block-number")]
+	pub block_number: Option<String>,
+
+	/// The block timestamp (TIMESTAMP)
+	#[structopt(long = "timestamp")]
+	pub timestamp: Option<String>,
+
+	/// The block beneficiary address (COINBASE)
+	#[structopt(long = "coinbase")]
+	pub coinbase: Option<String>,
+
+	/// The chain id (CHAINID)
+	#[structopt(long = "chain-id")]
+	pub chain_id: Option<String>,
+
+	/// The block difficulty (DIFFICULTY)
+	#[structopt(long = "difficulty")]
+	pub difficulty: Option<String>,
+
+	/// The block gas limit (GASLIMIT)
+	#[structopt(long = "block-gas-limit")]
+	pub block_gas_limit: Option<String>,
+
+	/// The block base fee (Wei) burnt from the fee of every transaction, the rest goes to the coinbase
+	#[structopt(long = "base-fee")]
+	pub base_fee: Option<String>,
+
+	/// The transaction gas price (Wei) seen by GASPRICE, defaults to the price of each transaction
+	#[structopt(long = "gas-price")]
+	pub gas_price: Option<String>,
+
+	/// The transaction origin address (ORIGIN), defaults to the sender of each transaction
+	#[structopt(long = "origin")]
+	pub origin: Option<String>,
+}
+
+impl VicinityOpts {
+	/// Build the vicinity from the env file, if any, overridden by the given flags.
+	pub fn vicinity(&self) -> BackendVicinity {
+		let mut vicinity = match &self.env {
+			Some(file) => {
+				let f = File::open(file).expect("--env file not found");
+				serde_json::from_reader(f).expect("--env file must be a valid block context")
+			},
+			None => BackendVicinity::default(),
+		};
+
+		let number = |name: &str, value: &Option<String>, target: &mut U256| {
+			if let Some(v) = value {
+				*target = U256::from_dec_str(v).unwrap_or_else(|_| panic!("--{} argument must be a valid number", name));
+			}
+		};
+		number("block-number", &self.block_number, &mut vicinity.block_number);
+		number("timestamp", &self.timestamp, &mut vicinity.block_timestamp);
+		number("chain-id", &self.chain_id, &mut vicinity.chain_id);
+		number("difficulty", &self.difficulty, &mut vicinity.block_difficulty);
+		number("block-gas-limit", &self.block_gas_limit, &mut vicinity.block_gas_limit);
+
+		let amount = |name: &str, value: &Option<String>, target: &mut U256| {
+			if let Some(v) = value {
+				*target = parse_amount(v).unwrap_or_else(|e| panic!("--{} argument is invalid: {}", name, e));
+			}
+		};
+		if let Some(v) = &self.gas_price {
+			vicinity.gas_price = Some(parse_amount(v).unwrap_or_else(|e| panic!("--gas-price argument is invalid: {}", e)));
+		}
+		amount("base-fee", &self.base_fee, &mut vicinity.block_base_fee);
+
+		if let Some(coinbase) = &self.coinbase {
+			vicinity.block_coinbase = H160::from_str(coinbase).expect("--coinbase argument must be a valid address");
+		}
+		if let Some(origin) = &self.origin {
+			vicinity.origin = Some(H160::from_str(origin).expect("--origin argument must be a valid address"));
+		}
+
+		vicinity
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::VicinityOpts;
+	use std::{env, fs, process};
+	use std::str::FromStr;
+	use ethereum_types::{H160, U256};
+	use structopt::StructOpt;
+
+	#[test]
+	fn test_env_file() {
+		let path = env::temp_dir().join(format!("bloom-evm-env-{}.json", process::id()));
+		fs::write(&path, r#"{
+			"chain_id": "0x1",
+			"block_number": "0x10",
+			"block_coinbase": "0x00000000000000000000000000000000000000cc",
+			"block_timestamp": "0x5f5e1000",
+			"block_base_fee": "0x7"
+		}"#).unwrap();
+		let path = path.to_str().unwrap();
+
+		let vicinity = VicinityOpts::from_iter(&["bloom-evm", "--env", path]).vicinity();
+		assert_eq!(vicinity.chain_id, U256::one());
+		assert_eq!(vicinity.block_number, U256::from(16));
+		assert_eq!(vicinity.block_coinbase, H160::from_low_u64_be(0xcc));
+		assert_eq!(vicinity.block_timestamp, U256::from(1600000000));
+		assert_eq!(vicinity.block_base_fee, U256::from(7));
+		// Fields missing from the file keep their default, the transactions set ORIGIN and GASPRICE.
+		assert_eq!(vicinity.block_gas_limit, U256::zero());
+		assert_eq!((vicinity.origin, vicinity.gas_price), (None, None));
+
+		// The flags override the fields of the file.
+		let vicinity = VicinityOpts::from_iter(&[
+			"bloom-evm", "--env", path, "--block-number", "20", "--coinbase", "00000000000000000000000000000000000000dd",
+		]).vicinity();
+		fs::remove_file(path).unwrap();
+		assert_eq!(vicinity.block_number, U256::from(20));
+		assert_eq!(vicinity.block_coinbase, H160::from_str("00000000000000000000000000000000000000dd").unwrap());
+		assert_eq!(vicinity.chain_id, U256::one());
+		assert_eq!(vicinity.block_timestamp, U256::from(1600000000));
+	}
+}
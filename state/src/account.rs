@@ -16,6 +16,7 @@ use std::fmt;
 use std::collections::{HashMap, BTreeMap};
 
 use crate::BasicAccount;
+use crate::units::format_ether;
 
 /// Boolean type for clean/dirty status.
 #[derive(PartialEq, Eq, Clone, Copy, Debug)]
@@ -256,12 +257,12 @@ impl fmt::Display for Account {
         let code_size = self.code_size.unwrap_or(0);
         match code_size {
             0 => {
-                f.debug_struct("External Account").field("balance",&self.balance)
+                f.debug_struct("External Account").field("balance",&format_args!("{} ({} ether)", self.balance, format_ether(self.balance)))
                     .field("nonce",&self.nonce)
                     .finish()
             },
             _ => {
-                f.debug_struct("Contract Account").field("balance",&self.balance)
+                f.debug_struct("Contract Account").field("balance",&format_args!("{} ({} ether)", self.balance, format_ether(self.balance)))
                     .field("nonce",&self.nonce)
                     .field("storage_root",&self.storage_root)
                     .field("code_hash",&self.code_hash)
@@ -0,0 +1,196 @@
+use std::fmt;
+
+use ethereum_types::{Bloom, H256, U256};
+use keccak_hash::keccak;
+use rlp::{Decodable, DecoderError, Encodable, Rlp, RlpStream};
+use trie_db::TrieMut;
+
+use crate::receipt::Receipt;
+
+/// Header of a block, sealing the transactions committed since its parent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Header {
+    /// Hash of the header of the parent block, zero for the first block.
+    pub parent_hash: H256,
+    pub number: U256,
+    pub timestamp: U256,
+    /// State root after the transactions of the block.
+    pub state_root: H256,
+    /// Root of the trie of the transaction hashes, keyed by their index.
+    pub transactions_root: H256,
+    /// Root of the trie of the receipts, keyed by their index.
+    pub receipts_root: H256,
+    /// Bloom filter of the logs of all the receipts.
+    pub logs_bloom: Bloom,
+    /// Gas used by all the transactions of the block.
+    pub gas_used: U256,
+}
+
+impl Header {
+    /// Header of the block following `parent` with the given receipts.
+    pub fn new(parent: Option<&Header>, timestamp: U256, state_root: H256, receipts: &[Receipt]) -> Self {
+        let mut logs_bloom = Bloom::default();
+        for receipt in receipts {
+            logs_bloom.accrue_bloom(&receipt.logs_bloom);
+        }
+
+        Header {
+            parent_hash: parent.map_or(H256::zero(), Header::hash),
+            number: parent.map_or(U256::zero(), |parent| parent.number + 1),
+            timestamp,
+            state_root,
+            transactions_root: ordered_trie_root(receipts.iter().map(|r| rlp::encode(&r.transaction_hash))),
+            receipts_root: ordered_trie_root(receipts.iter().map(rlp::encode)),
+            logs_bloom,
+            gas_used: receipts.iter().fold(U256::zero(), |gas, r| gas + r.gas_used),
+        }
+    }
+
+    /// Hash of the header, the keccak of its RLP encoding.
+    pub fn hash(&self) -> H256 {
+        keccak(rlp::encode(self))
+    }
+}
+
+/// Root of the trie of the items keyed by the RLP of their index.
+pub fn ordered_trie_root<I: IntoIterator<Item = Vec<u8>>>(items: I) -> H256 {
+    let mut memdb = journaldb::new_memory_db();
+    let mut root = H256::zero();
+    {
+        let mut trie = ethtrie::TrieDBMut::new(&mut memdb, &mut root);
+        for (index, item) in items.into_iter().enumerate() {
+            trie.insert(&rlp::encode(&index), &item).expect("Memory trie insertion cannot fail");
+        }
+    }
+    root
+}
+
+impl Encodable for Header {
+    fn rlp_append(&self, stream: &mut RlpStream) {
+        stream.begin_list(8);
+        stream.append(&self.parent_hash);
+        stream.append(&self.number);
+        stream.append(&self.timestamp);
+        stream.append(&self.state_root);
+        stream.append(&self.transactions_root);
+        stream.append(&self.receipts_root);
+        stream.append(&self.logs_bloom);
+        stream.append(&self.gas_used);
+    }
+}
+
+impl Decodable for Header {
+    fn decode(rlp: &Rlp) -> Result<Self, DecoderError> {
+        if rlp.item_count()? != 8 {
+            return Err(DecoderError::RlpIncorrectListLen);
+        }
+
+        Ok(Header {
+            parent_hash: rlp.val_at(0)?,
+            number: rlp.val_at(1)?,
+            timestamp: rlp.val_at(2)?,
+            state_root: rlp.val_at(3)?,
+            transactions_root: rlp.val_at(4)?,
+            receipts_root: rlp.val_at(5)?,
+            logs_bloom: rlp.val_at(6)?,
+            gas_used: rlp.val_at(7)?,
+        })
+    }
+}
+
+impl fmt::Display for Header {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "number: {}", self.number)?;
+        writeln!(f, "hash: {:?}", self.hash())?;
+        writeln!(f, "parent hash: {:?}", self.parent_hash)?;
+        writeln!(f, "timestamp: {}", self.timestamp)?;
+        writeln!(f, "state root: {:?}", self.state_root)?;
+        writeln!(f, "transactions root: {:?}", self.transactions_root)?;
+        writeln!(f, "receipts root: {:?}", self.receipts_root)?;
+        writeln!(f, "gas used: {}", self.gas_used)?;
+        write!(f, "logs bloom: {:?}", self.logs_bloom)
+    }
+}
+
+/// A sealed block: its header, its transactions and the commit of the state it seals.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Block {
+    pub header: Header,
+    /// Hashes of the transactions of the block.
+    pub transactions: Vec<H256>,
+    /// Number of the last commit of the state sealed into the block.
+    pub commit: U256,
+}
+
+impl Encodable for Block {
+    fn rlp_append(&self, stream: &mut RlpStream) {
+        stream.begin_list(3);
+        stream.append(&self.header);
+        stream.append_list(&self.transactions);
+        stream.append(&self.commit);
+    }
+}
+
+impl Decodable for Block {
+    fn decode(rlp: &Rlp) -> Result<Self, DecoderError> {
+        if rlp.item_count()? != 3 {
+            return Err(DecoderError::RlpIncorrectListLen);
+        }
+
+        Ok(Block {
+            header: rlp.val_at(0)?,
+            transactions: rlp.list_at(1)?,
+            commit: rlp.val_at(2)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ordered_trie_root, Block, Header};
+    use crate::receipt::Receipt;
+    use ethereum_types::{H160, H256, U256};
+    use evm::backend::Log;
+    use keccak_hash::KECCAK_NULL_RLP;
+
+    #[test]
+    fn test_ordered_trie_root() {
+        assert_eq!(ordered_trie_root(Vec::new()), KECCAK_NULL_RLP);
+        assert_ne!(ordered_trie_root(vec![vec![1u8]]), ordered_trie_root(vec![vec![2u8]]));
+        assert_ne!(ordered_trie_root(vec![vec![1u8], vec![2]]), ordered_trie_root(vec![vec![2u8], vec![1]]));
+    }
+
+    #[test]
+    fn test_block() {
+        let log = Log {
+            address: H160::from_low_u64_be(1),
+            topics: vec![H256::from_low_u64_be(2)],
+            data: vec![],
+        };
+        let receipts = vec![
+            Receipt::new(H256::from_low_u64_be(3), true, U256::from(21000), U256::from(21000), None, vec![log]),
+            Receipt::new(H256::from_low_u64_be(4), false, U256::from(30000), U256::from(30000), None, vec![]),
+        ];
+
+        let first = Header::new(None, U256::from(10), H256::from_low_u64_be(5), &[]);
+        assert_eq!(first.number, U256::zero());
+        assert_eq!(first.parent_hash, H256::zero());
+        assert_eq!(first.transactions_root, KECCAK_NULL_RLP);
+
+        let second = Header::new(Some(&first), U256::from(20), H256::from_low_u64_be(6), &receipts);
+        assert_eq!(second.number, U256::one());
+        assert_eq!(second.parent_hash, first.hash());
+        assert_eq!(second.gas_used, U256::from(51000));
+        assert_eq!(second.logs_bloom, receipts[0].logs_bloom);
+        assert_ne!(second.hash(), first.hash());
+
+        let block = Block {
+            header: second,
+            transactions: receipts.iter().map(|r| r.transaction_hash).collect(),
+            commit: U256::from(7),
+        };
+        let decoded: Block = rlp::decode(&rlp::encode(&block)).unwrap();
+        assert_eq!(decoded, block);
+        assert_eq!(decoded.header.hash(), block.header.hash());
+    }
+}
@@ -7,9 +7,15 @@ use ethereum_types::{H160,H256,U256};
 mod state;
 mod account_db;
 mod account;
+mod receipt;
+mod block;
+pub mod units;
 
 pub use state::State;
+pub use account::Account;
 pub use account_db::Factory as AccountFactory;
+pub use receipt::{Receipt, logs_bloom};
+pub use block::{Block, Header, ordered_trie_root};
 use ethtrie;
 
 
@@ -17,12 +23,18 @@ use ethtrie;
 pub const COLUMN_COUNT: u32 = 9;
 pub const COL_STATE: u32 = 0;
 pub const COL_BLOCK: u32 = 1;
+pub const COL_RECEIPT: u32 = 2;
+pub const COL_ABI: u32 = 3;
+pub const COL_HEADER: u32 = 4;
 
-#[derive(Clone,Debug,Eq,PartialEq)]
+#[derive(Clone,Debug,Default,Eq,PartialEq)]
 #[cfg_attr(feature = "with-serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "with-serde", serde(default))]
 pub struct BackendVicinity {
-    pub gas_price: U256,
-    pub origin: H160,
+    /// Gas price seen by GASPRICE, the one of each transaction when `None`.
+    pub gas_price: Option<U256>,
+    /// Origin seen by ORIGIN, the sender of each transaction when `None`.
+    pub origin: Option<H160>,
     pub chain_id: U256,
     pub block_hashes: Vec<H256>,
     pub block_number: U256,
@@ -30,6 +42,8 @@ pub struct BackendVicinity {
     pub block_timestamp: U256,
     pub block_difficulty: U256,
     pub block_gas_limit: U256,
+    /// Base fee per gas burnt by every transaction, the rest of the fee goes to the coinbase.
+    pub block_base_fee: U256,
 }
 
 
@@ -0,0 +1,165 @@
+use std::fmt;
+
+use ethereum_types::{Bloom, BloomInput, H160, H256, U256};
+use evm::backend::Log;
+use rlp::{Decodable, DecoderError, Encodable, Rlp, RlpStream};
+
+/// Receipt of an executed transaction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Receipt {
+    /// Hash of the transaction.
+    pub transaction_hash: H256,
+    /// Whether the transaction succeeded.
+    pub status: bool,
+    /// Gas used by the transaction.
+    pub gas_used: U256,
+    /// Gas used by the transaction and all the ones before it in the same commit, or in the same
+    /// block for the receipts of a block.
+    pub cumulative_gas_used: U256,
+    /// Address of the contract created by the transaction.
+    pub contract_address: Option<H160>,
+    /// Logs emitted by the transaction.
+    pub logs: Vec<Log>,
+    /// Bloom filter of the logs.
+    pub logs_bloom: Bloom,
+}
+
+impl Receipt {
+    pub fn new(
+        transaction_hash: H256,
+        status: bool,
+        gas_used: U256,
+        cumulative_gas_used: U256,
+        contract_address: Option<H160>,
+        logs: Vec<Log>,
+    ) -> Self {
+        let logs_bloom = logs_bloom(&logs);
+
+        Receipt {
+            transaction_hash,
+            status,
+            gas_used,
+            cumulative_gas_used,
+            contract_address,
+            logs,
+            logs_bloom,
+        }
+    }
+}
+
+/// Calculate the bloom filter of the logs, accruing each address and topic.
+pub fn logs_bloom(logs: &[Log]) -> Bloom {
+    let mut bloom = Bloom::default();
+    for log in logs {
+        bloom.accrue(BloomInput::Raw(log.address.as_bytes()));
+        for topic in &log.topics {
+            bloom.accrue(BloomInput::Raw(topic.as_bytes()));
+        }
+    }
+    bloom
+}
+
+impl Encodable for Receipt {
+    fn rlp_append(&self, stream: &mut RlpStream) {
+        stream.begin_list(7);
+        stream.append(&self.transaction_hash);
+        stream.append(&self.status);
+        stream.append(&self.gas_used);
+        stream.append(&self.cumulative_gas_used);
+        match self.contract_address {
+            Some(ref address) => { stream.append(address); },
+            None => { stream.append_empty_data(); },
+        }
+        stream.append(&self.logs_bloom);
+        stream.begin_list(self.logs.len());
+        for log in &self.logs {
+            stream.begin_list(3);
+            stream.append(&log.address);
+            stream.append_list(&log.topics);
+            stream.append(&log.data);
+        }
+    }
+}
+
+impl Decodable for Receipt {
+    fn decode(rlp: &Rlp) -> Result<Self, DecoderError> {
+        if rlp.item_count()? != 7 {
+            return Err(DecoderError::RlpIncorrectListLen);
+        }
+
+        let contract_address = match rlp.at(4)?.is_empty() {
+            true => None,
+            false => Some(rlp.val_at(4)?),
+        };
+
+        let mut logs = Vec::new();
+        for log in rlp.at(6)?.iter() {
+            logs.push(Log {
+                address: log.val_at(0)?,
+                topics: log.list_at(1)?,
+                data: log.val_at(2)?,
+            });
+        }
+
+        Ok(Receipt {
+            transaction_hash: rlp.val_at(0)?,
+            status: rlp.val_at(1)?,
+            gas_used: rlp.val_at(2)?,
+            cumulative_gas_used: rlp.val_at(3)?,
+            contract_address,
+            logs,
+            logs_bloom: rlp.val_at(5)?,
+        })
+    }
+}
+
+impl fmt::Display for Receipt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "transaction hash: {:?}", self.transaction_hash)?;
+        writeln!(f, "status: {}", if self.status { "1 (success)" } else { "0 (failure)" })?;
+        writeln!(f, "gas used: {}", self.gas_used)?;
+        writeln!(f, "cumulative gas used: {}", self.cumulative_gas_used)?;
+        if let Some(address) = self.contract_address {
+            writeln!(f, "contract address: {:?}", address)?;
+        }
+        for (i, log) in self.logs.iter().enumerate() {
+            writeln!(f, "log {}: address: {:?}, topics: {:?}, data: 0x{}", i, log.address, log.topics, hex::encode(&log.data))?;
+        }
+        write!(f, "logs bloom: {:?}", self.logs_bloom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Receipt;
+    use ethereum_types::{H160, H256, U256};
+    use evm::backend::Log;
+
+    #[test]
+    fn test_receipt_rlp() {
+        let log = Log {
+            address: H160::from_low_u64_be(1),
+            topics: vec![H256::from_low_u64_be(2)],
+            data: vec![0xde, 0xad],
+        };
+        let created = Receipt::new(H256::from_low_u64_be(3), true, U256::from(53000), U256::from(53000), Some(H160::from_low_u64_be(4)), vec![log]);
+        let failed = Receipt::new(H256::from_low_u64_be(5), false, U256::from(21000), U256::from(74000), None, vec![]);
+
+        for receipt in vec![created, failed] {
+            let decoded: Receipt = rlp::decode(&rlp::encode(&receipt)).unwrap();
+            assert_eq!(decoded, receipt);
+        }
+    }
+
+    #[test]
+    fn test_logs_bloom() {
+        let log = Log {
+            address: H160::from_low_u64_be(1),
+            topics: vec![H256::from_low_u64_be(2)],
+            data: vec![],
+        };
+        let receipt = Receipt::new(H256::zero(), true, U256::zero(), U256::zero(), None, vec![log.clone()]);
+        assert!(receipt.logs_bloom.contains_input(ethereum_types::BloomInput::Raw(log.address.as_bytes())));
+        assert!(!receipt.logs_bloom.contains_input(ethereum_types::BloomInput::Raw(H160::from_low_u64_be(9).as_bytes())));
+    }
+}
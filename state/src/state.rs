@@ -26,6 +26,8 @@ pub struct State<'vicinity> {
     root: H256,
     factories: Factories,
     logs: Vec<Log>,
+    /// Sender and gas price of the executing transaction.
+    transaction: (H160, U256),
 }
 
 impl<'vicinity> Clone for State<'vicinity> {
@@ -36,6 +38,7 @@ impl<'vicinity> Clone for State<'vicinity> {
             root: self.root.clone(),
             factories: self.factories.clone(),
             logs: self.logs.clone(),
+            transaction: self.transaction,
         }
     }
 }
@@ -50,6 +53,7 @@ impl<'vicinity> State<'vicinity> {
             root,
             factories,
             logs: vec![],
+            transaction: Default::default(),
         }
     }
 
@@ -64,11 +68,23 @@ impl<'vicinity> State<'vicinity> {
             root,
             factories,
             logs: vec![],
+            transaction: Default::default(),
         };
 
         Ok(state)
     }
 
+    /// Set the sender and gas price of the transaction about to execute, seen by ORIGIN and
+    /// GASPRICE unless the vicinity sets them.
+    pub fn set_transaction(&mut self, origin: H160, gas_price: U256) {
+        self.transaction = (origin, gas_price);
+    }
+
+    /// Base fee per gas of the block, burnt from the fee of every transaction.
+    pub fn block_base_fee(&self) -> U256 {
+        self.vicinity.block_base_fee
+    }
+
     pub fn get_account(&self,address: H160) -> Account {
 
         let db = &self.db.as_hash_db();
@@ -110,14 +126,21 @@ impl<'vicinity> State<'vicinity> {
         account.get_storage(accountdb.as_hash_db(), storage_root).unwrap()
     }
 
-    pub fn commit(&mut self) -> H256 {
+    /// Journal the pending changes under `era`, identified by the new state root.
+    ///
+    /// `canon` is the era and root which a pruning database may now treat as canonical.
+    pub fn commit(&mut self, era: u64, canon: Option<(u64, H256)>) -> H256 {
 //        let res = self.db.drain_transaction_overlay().unwrap();
 //        self.db.backing().write(res);
 //        self.root.clone()
         let mut batch = DBTransaction::new();
-        let id = H256::default();
-        self.db.journal_under(&mut batch,0,&id);
-        self.db.backing().write(batch);
+        let id = self.root.clone();
+        self.db.journal_under(&mut batch, era, &id).unwrap();
+        if let Some((canon_era, canon_id)) = canon {
+            self.db.mark_canonical(&mut batch, canon_era, &canon_id).unwrap();
+        }
+        self.db.backing().write(batch).unwrap();
+        self.db.flush();
         self.root.clone()
     }
 
@@ -155,8 +178,8 @@ impl<'vicinity> State<'vicinity> {
 }
 
 impl <'vicinity> Backend for State<'vicinity> {
-    fn gas_price(&self) -> U256 {self.vicinity.gas_price}
-    fn origin(&self) -> H160 {self.vicinity.origin}
+    fn gas_price(&self) -> U256 {self.vicinity.gas_price.unwrap_or(self.transaction.1)}
+    fn origin(&self) -> H160 {self.vicinity.origin.unwrap_or(self.transaction.0)}
     fn block_hash(&self, number: U256) -> H256  {
         if number >= self.vicinity.block_number ||
             self.vicinity.block_number - number - U256::one() >= U256::from(self.vicinity.block_hashes.len()){
@@ -384,8 +407,8 @@ mod tests {
         let gas_limit = 1000000u32;
 
         let vicinity = BackendVicinity {
-            gas_price: U256::zero(),
-            origin: H160::zero(),
+            gas_price: None,
+            origin: None,
             chain_id: U256::zero(),
             block_hashes: Vec::new(),
             block_number: U256::zero(),
@@ -393,6 +416,7 @@ mod tests {
             block_timestamp: U256::zero(),
             block_difficulty: U256::zero(),
             block_gas_limit: U256::zero(),
+            block_base_fee: U256::zero(),
         };
 
         let trie_factory =  ethtrie::TrieFactory::new(trie_spec,trie_layout);
@@ -420,7 +444,7 @@ mod tests {
 
         let acc = state.get_account(address);
         assert_eq!(*acc.balance(),value);
-        let root = state.commit();
+        let root = state.commit(1, None);
         println!("root={}",root);
 
     }
@@ -0,0 +1,85 @@
+use ethereum_types::U256;
+use std::str::FromStr;
+
+/// Decimals of the units of an amount, from the largest.
+const UNITS: [(&str, usize); 3] = [("ether", 18), ("gwei", 9), ("wei", 0)];
+
+/// Parse an amount of wei: plain wei, hex with a 0x prefix, or a decimal number followed by a
+/// `wei`, `gwei` or `ether` unit such as `1.5ether` or `20gwei`.
+pub fn parse_amount(s: &str) -> Result<U256, String> {
+    let s = s.trim();
+    let invalid = || format!("Invalid amount {}, expected wei, 0x hex or a number with a wei, gwei or ether unit", s);
+
+    if let Some(hex) = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        if hex.is_empty() || hex.len() > 64 {
+            return Err(invalid());
+        }
+        return U256::from_str(hex).map_err(|_| invalid());
+    }
+
+    let lower = s.to_ascii_lowercase();
+    let (number, decimals) = UNITS.iter()
+        .find_map(|(unit, decimals)| lower.strip_suffix(unit).map(|number| (number.trim_end(), *decimals)))
+        .unwrap_or((&lower, 0));
+    let (integer, fraction) = match number.find('.') {
+        Some(dot) => (&number[..dot], &number[dot + 1..]),
+        None => (number, ""),
+    };
+    if integer.is_empty() && fraction.is_empty() {
+        return Err(invalid());
+    }
+    if fraction.len() > decimals {
+        return Err(format!("Amount {} is not a whole number of wei", s));
+    }
+
+    let digits = format!("{}{}{}", integer, fraction, "0".repeat(decimals - fraction.len()));
+    U256::from_dec_str(&digits).map_err(|_| invalid())
+}
+
+/// Format an amount of wei in ether, without trailing zeros.
+pub fn format_ether(value: U256) -> String {
+    let ether = U256::exp10(18);
+    // The fraction is below 10^18 and fits a u64.
+    let fraction = format!("{:018}", (value % ether).low_u64());
+    let fraction = fraction.trim_end_matches('0');
+    match fraction.is_empty() {
+        true => format!("{}", value / ether),
+        false => format!("{}.{}", value / ether, fraction),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_ether, parse_amount};
+    use ethereum_types::U256;
+
+    #[test]
+    fn test_parse_amount() {
+        assert_eq!(parse_amount("1000"), Ok(U256::from(1000)));
+        assert_eq!(parse_amount("1000wei"), Ok(U256::from(1000)));
+        assert_eq!(parse_amount("0x3e8"), Ok(U256::from(1000)));
+        assert_eq!(parse_amount("20gwei"), Ok(U256::from(20_000_000_000u64)));
+        assert_eq!(parse_amount("1.5ether"), Ok(U256::from(1_500_000_000_000_000_000u64)));
+        assert_eq!(parse_amount("1.5 ETHER"), Ok(U256::from(1_500_000_000_000_000_000u64)));
+        assert_eq!(parse_amount(".5gwei"), Ok(U256::from(500_000_000)));
+
+        assert!(parse_amount("1.5").is_err());
+        assert!(parse_amount("0.0000000001gwei").is_err());
+        assert!(parse_amount("ether").is_err());
+        assert!(parse_amount("1.2.3ether").is_err());
+        assert!(parse_amount("-1").is_err());
+        assert!(parse_amount("0x").is_err());
+        assert!(parse_amount(&format!("0x1{}", "0".repeat(64))).is_err());
+        assert!(parse_amount(&format!("{}", U256::max_value())).is_ok());
+        assert!(parse_amount(&format!("{}0", U256::max_value())).is_err());
+    }
+
+    #[test]
+    fn test_format_ether() {
+        assert_eq!(format_ether(U256::zero()), "0");
+        assert_eq!(format_ether(U256::exp10(18)), "1");
+        assert_eq!(format_ether(U256::from(1_500_000_000_000_000_000u64)), "1.5");
+        assert_eq!(format_ether(U256::one()), "0.000000000000000001");
+        assert_eq!(parse_amount(&format!("{}ether", format_ether(U256::from(123_456_789)))), Ok(U256::from(123_456_789)));
+    }
+}